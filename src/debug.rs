@@ -33,13 +33,21 @@ impl DebugPrinter {
         DebugPrinter { debug_mode }
     }
 
-    pub fn log_entry(&self, name: &str, args: &[Value]) {
+    pub fn log_entry(&self, name: &str, args: &[Value], kwargs: &[(String, Value)]) {
         if !self.debug_mode {
             return;
         }
         let frame = CallFrame {
             function_name: name.to_string(),
-            args: args.iter().map(|arg| format!("{:?}", arg)).collect(),
+            args: args
+                .iter()
+                .map(|arg| format!("{:?}", arg))
+                .chain(
+                    kwargs
+                        .iter()
+                        .map(|(name, arg)| format!("{} = {:?}", name, arg)),
+                )
+                .collect(),
             start_time: Instant::now(),
         };
         CALL_STACK.with(|stack| {
@@ -91,7 +99,7 @@ impl DebugPrinter {
         let indent = "  ".repeat(depth);
         match value {
             Value::Primitive(p) => println!("{}Value: {:?}", indent, p),
-            Value::Function(name, params, body, _) => {
+            Value::Function(name, params, body, ..) => {
                 println!("{}Function: {} ({})", indent, name, params.join(", "));
                 println!("{}Body:", indent);
                 body.iter()
@@ -108,6 +116,18 @@ impl DebugPrinter {
             Value::Ffi(s) => {
                 println!("{}Foreign Function Interface: {:?}", indent, s);
             }
+            Value::Module(s) => {
+                println!("{}Module: {:?}", indent, s);
+            }
+            Value::Glob(candidates) => {
+                println!("{}Glob Import: {:?}", indent, candidates);
+            }
+            Value::Pending(_) => {
+                println!("{}Pending Foreign Call", indent);
+            }
+            Value::Builtin(builtin) => {
+                println!("{}Builtin: {}", indent, builtin.name());
+            }
         }
     }
 
@@ -118,8 +138,14 @@ impl DebugPrinter {
         let indent = "  ".repeat(depth);
         match expr {
             Expr::Primitive(p) => println!("{}Primitive: {:?}", indent, p),
+            Expr::Array(elements) => {
+                println!("{}Array:", indent);
+                elements
+                    .iter()
+                    .for_each(|e| self.log_expr(e, _env, depth + 1));
+            }
             Expr::Variable(name) => println!("{}Variable: {}", indent, name),
-            Expr::FunctionDef(name, params, body) => {
+            Expr::FunctionDef(name, params, body, _) => {
                 println!(
                     "{}Function Definition: {} ({})",
                     indent,
@@ -129,7 +155,7 @@ impl DebugPrinter {
                 println!("{}Body:", indent);
                 body.iter().for_each(|e| self.log_expr(e, _env, depth + 1));
             }
-            Expr::FunctionCall(func, args) => {
+            Expr::FunctionCall(func, args, kwargs) => {
                 println!("{}Function Call:", indent);
                 self.log_expr(func, _env, depth + 1);
                 println!("{}Arguments:", indent);
@@ -137,6 +163,10 @@ impl DebugPrinter {
                     println!("{}Arg {}:", indent, i);
                     self.log_expr(arg, _env, depth + 2);
                 }
+                for (name, arg) in kwargs {
+                    println!("{}Arg {}:", indent, name);
+                    self.log_expr(arg, _env, depth + 2);
+                }
             }
             Expr::Return(e) => {
                 println!("{}Return:", indent);
@@ -146,15 +176,13 @@ impl DebugPrinter {
                 println!("{}Assignment: {}", indent, name);
                 self.log_expr(e, _env, depth + 1);
             }
-            Expr::FFIDecl(module, name, given_name) => match given_name {
-                Some(given_name) => {
-                    println!(
-                        "{}FFI Declaration: from {} use {} as {}",
-                        indent, module, name, given_name
-                    );
+            Expr::FFIDecl(..) => println!("{}{}", indent, expr),
+            Expr::ModuleDecl(module, alias) => match alias {
+                Some(alias) => {
+                    println!("{}Module Declaration: use {} as {}", indent, module, alias);
                 }
                 None => {
-                    println!("{}FFI Declaration: from {} use {}", indent, module, name);
+                    println!("{}Module Declaration: use {}", indent, module);
                 }
             },
             Expr::NotationDecl(pattern, expansion) => {
@@ -165,6 +193,32 @@ impl DebugPrinter {
             Expr::InfixOp(left, op, right) => {
                 println!("{}Infix Operation: {} {} {}", indent, left, op, right);
             }
+            Expr::Try(body, clauses) => {
+                println!("{}Try:", indent);
+                body.iter().for_each(|e| self.log_expr(e, _env, depth + 1));
+                for clause in clauses {
+                    println!("{}{}:", indent, clause);
+                    clause
+                        .body
+                        .iter()
+                        .for_each(|e| self.log_expr(e, _env, depth + 1));
+                }
+            }
+            Expr::Record(base, fields) => {
+                println!("{}Record:", indent);
+                if let Some(base) = base {
+                    println!("{}Updating:", indent);
+                    self.log_expr(base, _env, depth + 1);
+                }
+                for (name, value) in fields {
+                    println!("{}Field {}:", indent, name);
+                    self.log_expr(value, _env, depth + 2);
+                }
+            }
+            Expr::Field(record, name) => {
+                println!("{}Field Access: {}", indent, name);
+                self.log_expr(record, _env, depth + 1);
+            }
         }
     }
 
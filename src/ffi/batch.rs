@@ -0,0 +1,168 @@
+// ffi/batch.rs
+//
+// a batchable function takes a list of argument lists and returns a list of results, one per
+// call: `embed([["a"], ["b"]])` answers `embed("a")` and `embed("b")`. under `--parallel`,
+// calls to it that are pending at the same time are sent together, as soon as the batch is
+// full or a short window after the first of them. calls with different named arguments are
+// never mixed: the named arguments are passed once, to the whole batch.
+
+use super::json::kwargs_to_json;
+use super::limit::{call_limited, Limiter};
+use super::{invoke, FFIError, SharedProtocol};
+use crate::syntax::Primitive;
+use futures::channel::oneshot;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Batching {
+    pub max_size: Option<usize>,
+    pub window: Duration, // how long the first call of a batch waits for others
+}
+
+pub const DEFAULT_WINDOW: Duration = Duration::from_millis(10);
+
+type Reply = oneshot::Sender<Result<Primitive, FFIError>>;
+
+// calls waiting to be sent, all with the same named arguments
+struct Queue {
+    id: usize,
+    kwargs: Vec<(String, Primitive)>,
+    limiters: Vec<Arc<Limiter>>, // a batch is one call as far as limits go
+    calls: Vec<(Vec<Primitive>, Reply)>,
+}
+
+pub struct Batcher {
+    pub batching: Batching,
+    function: String, // fully qualified
+    protocol: SharedProtocol,
+    queues: Mutex<HashMap<String, Queue>>, // named arguments as JSON -> their queue
+    next_id: AtomicUsize,
+}
+
+impl Batcher {
+    pub fn new(batching: Batching, function: &str, protocol: SharedProtocol) -> Self {
+        Batcher {
+            batching,
+            function: function.to_string(),
+            protocol,
+            queues: Mutex::new(HashMap::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    // must run on the worker runtime
+    pub async fn submit(
+        self: Arc<Self>,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+        limiters: Vec<Arc<Limiter>>,
+    ) -> Result<Primitive, FFIError> {
+        let (reply, result) = oneshot::channel();
+        let key = kwargs_to_json(&kwargs).to_string();
+        let full = {
+            let mut queues = self
+                .queues
+                .lock()
+                .map_err(|_| FFIError::CallError(format!("Batch poisoned: {}", self.function)))?;
+            let queue = queues.entry(key.clone()).or_insert_with(|| Queue {
+                id: self.next_id.fetch_add(1, Ordering::SeqCst),
+                kwargs,
+                limiters,
+                calls: Vec::new(),
+            });
+            queue.calls.push((args, reply));
+
+            if self
+                .batching
+                .max_size
+                .is_some_and(|max_size| queue.calls.len() >= max_size)
+            {
+                queues.remove(&key)
+            } else {
+                if queue.calls.len() == 1 {
+                    tokio::spawn(Arc::clone(&self).send_after_window(key, queue.id));
+                }
+                None
+            }
+        };
+        if let Some(queue) = full {
+            tokio::spawn(Arc::clone(&self).send(queue));
+        }
+
+        result.await.unwrap_or_else(|_| {
+            Err(FFIError::CallError(format!(
+                "Batch of {} was dropped before finishing",
+                self.function
+            )))
+        })
+    }
+
+    // a queue that filled up in the meantime was already sent, and a new one has another id
+    async fn send_after_window(self: Arc<Self>, key: String, id: usize) {
+        tokio::time::sleep(self.batching.window).await;
+        let queue = self
+            .queues
+            .lock()
+            .ok()
+            .and_then(|mut queues| match queues.get(&key) {
+                Some(queue) if queue.id == id => queues.remove(&key),
+                _ => None,
+            });
+        if let Some(queue) = queue {
+            self.send(queue).await;
+        }
+    }
+
+    async fn send(self: Arc<Self>, queue: Queue) {
+        let (args, replies): (Vec<_>, Vec<_>) = queue.calls.into_iter().unzip();
+        let count = args.len();
+        let args = pack(args);
+        let result = call_limited(&self.function, &queue.limiters, || {
+            invoke(
+                Arc::clone(&self.protocol),
+                self.function.clone(),
+                args.clone(),
+                queue.kwargs.clone(),
+            )
+        })
+        .await;
+
+        match result.and_then(|results| unpack(&self.function, results, count)) {
+            Ok(results) => {
+                for (reply, result) in replies.into_iter().zip(results) {
+                    let _ = reply.send(Ok(result));
+                }
+            }
+            // every call of a failed batch fails with its error
+            Err(error) => {
+                for reply in replies {
+                    let _ = reply.send(Err(error.clone()));
+                }
+            }
+        }
+    }
+}
+
+// the single argument of a batched call
+pub fn pack(calls: Vec<Vec<Primitive>>) -> Vec<Primitive> {
+    vec![Primitive::Array(
+        calls.into_iter().map(Primitive::Array).collect(),
+    )]
+}
+
+pub fn unpack(
+    function: &str,
+    results: Primitive,
+    expected: usize,
+) -> Result<Vec<Primitive>, FFIError> {
+    match results {
+        Primitive::Array(results) if results.len() == expected => Ok(results),
+        other => Err(FFIError::CallError(format!(
+            "Batched function {} must return a list of {} results, got {}",
+            function, expected, other
+        ))),
+    }
+}
@@ -0,0 +1,298 @@
+// ffi/c.rs
+//
+// native functions from shared libraries. symbols carry no type information, so every
+// function needs a declared signature: `from c.libm use cos(float) -> float`.
+// `int` is a C `int`, `long` a `long`, `i64` an `int64_t` and `size_t` a `size_t`; `float` is a
+// `double`, `bool` a C `bool` and `string` a `const char *`.
+
+use super::search_path::SearchPath;
+use super::{FFIError, FFIProtocol};
+use crate::syntax::{Primitive, Signature, Type};
+use libffi::middle::{arg, Arg, Cif, CodePtr};
+use libloading::Library;
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::env::consts::DLL_EXTENSION;
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_long, c_void, CStr, CString};
+use std::fs;
+use std::path::PathBuf;
+
+const SYSTEM_LIBRARY_DIRS: &[&str] = &[
+    "/lib",
+    "/usr/lib",
+    "/usr/local/lib",
+    "/lib64",
+    "/usr/lib64",
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib/aarch64-linux-gnu",
+    "/usr/lib/aarch64-linux-gnu",
+];
+
+// owned storage for one marshalled argument, kept alive for the duration of the call
+enum CValue {
+    Int(c_int),
+    Long(c_long),
+    I64(i64),
+    SizeT(usize),
+    Float(f64),
+    Bool(u8),
+    String(CString, *const c_char),
+}
+
+pub struct NativeFFI {
+    libraries: HashMap<String, Library>,
+    functions: HashMap<String, (usize, Signature)>, // library.function -> (address, signature)
+    search_path: SearchPath,
+}
+
+impl NativeFFI {
+    pub fn new(search_path: SearchPath) -> Self {
+        NativeFFI {
+            libraries: HashMap::new(),
+            functions: HashMap::new(),
+            search_path,
+        }
+    }
+
+    // the search path first, then the dynamic loader, then versioned system libraries
+    // (`libm.so` is often a linker script, `libm.so.6` the real library)
+    fn open_library(&self, module_path: &str) -> Result<Library, Box<dyn Error>> {
+        let candidates = self.search_path.candidates("c", module_path, DLL_EXTENSION);
+        if let Some(path) = candidates.iter().find(|path| path.is_file()) {
+            return Ok(unsafe { Library::new(path) }?);
+        }
+
+        let name = module_path.rsplit('.').next().unwrap_or(module_path);
+        let file_name = format!("{}.{}", name, DLL_EXTENSION);
+        if let Ok(library) = unsafe { Library::new(&file_name) } {
+            return Ok(library);
+        }
+
+        let versioned_prefix = format!("{}.", file_name);
+        let mut versioned: Vec<PathBuf> = SYSTEM_LIBRARY_DIRS
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(Result::ok))
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&versioned_prefix)
+            })
+            .map(|entry| entry.path())
+            .collect();
+        versioned.sort();
+        for path in &versioned {
+            if let Ok(library) = unsafe { Library::new(path) } {
+                return Ok(library);
+            }
+        }
+
+        let mut searched = candidates;
+        searched.push(PathBuf::from(file_name));
+        Err(FFIError::ModuleNotFound(format!("c.{}", module_path), searched).into())
+    }
+}
+
+fn marshal(value: &Primitive, ty: &Type) -> Result<CValue, FFIError> {
+    match (ty, value) {
+        (ty, Primitive::Int(_) | Primitive::BigInt(_)) if ty.is_integer() => {
+            let i = match value {
+                Primitive::BigInt(i) => i.clone(),
+                Primitive::Int(i) => BigInt::from(*i),
+                _ => unreachable!("matched an integer"),
+            };
+            let fits = match ty {
+                Type::Int => c_int::try_from(&i).map(CValue::Int),
+                Type::Long => c_long::try_from(&i).map(CValue::Long),
+                Type::I64 => i64::try_from(&i).map(CValue::I64),
+                _ => usize::try_from(&i).map(CValue::SizeT),
+            };
+            fits.map_err(|_| FFIError::CallError(format!("{} does not fit in a C {}", i, ty)))
+        }
+        (Type::Float, Primitive::Float(f)) => Ok(CValue::Float(*f)),
+        (Type::Float, Primitive::Int(i)) => Ok(CValue::Float(*i as f64)),
+        (Type::Float, Primitive::BigInt(i)) => Ok(CValue::Float(
+            i.to_string().parse().expect("an integer is a valid float"),
+        )),
+        (Type::Bool, Primitive::Bool(b)) => Ok(CValue::Bool(*b as u8)),
+        (Type::String, Primitive::String(s)) => {
+            let owned = CString::new(s.as_str()).map_err(|e| {
+                FFIError::CallError(format!("String passed to C contains a NUL byte: {}", e))
+            })?;
+            let ptr = owned.as_ptr();
+            Ok(CValue::String(owned, ptr))
+        }
+        (ty, value) => Err(FFIError::CallError(format!(
+            "Expected {} argument, got {}",
+            ty, value
+        ))),
+    }
+}
+
+fn ffi_type(ty: &Type) -> libffi::middle::Type {
+    match ty {
+        Type::Int => libffi::middle::Type::c_int(),
+        Type::Long => libffi::middle::Type::c_long(),
+        Type::I64 => libffi::middle::Type::i64(),
+        Type::SizeT => libffi::middle::Type::usize(),
+        Type::Float => libffi::middle::Type::f64(),
+        Type::Bool => libffi::middle::Type::u8(),
+        Type::String => libffi::middle::Type::pointer(),
+        Type::None | Type::Any | Type::Array(_) | Type::Record | Type::Function(..) => {
+            unreachable!("C signatures are checked to be scalar when declared")
+        }
+    }
+}
+
+fn is_scalar(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty, Type::Float | Type::Bool | Type::String)
+}
+
+impl FFIProtocol for NativeFFI {
+    fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.libraries.contains_key(module_path) {
+            let library = self.open_library(module_path)?;
+            self.libraries.insert(module_path.to_string(), library);
+        }
+
+        let prefix = format!("{}.", module_path);
+        Ok(self
+            .functions
+            .keys()
+            .filter_map(|path| path.strip_prefix(&prefix).map(String::from))
+            .collect())
+    }
+
+    // libraries from the system are not tracked
+    fn source(&self, module_path: &str) -> Option<PathBuf> {
+        self.search_path
+            .resolve("c", module_path, DLL_EXTENSION)
+            .ok()
+    }
+
+    fn declare(
+        &mut self,
+        func_path: &str,
+        signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (module_path, func_name) = func_path
+            .rsplit_once('.')
+            .ok_or_else(|| FFIError::FunctionNotFound(func_path.to_string()))?;
+        let signature = signature.ok_or_else(|| {
+            FFIError::LoadError(format!(
+                "C function {} needs a signature, e.g. `from c.{} use {}(float) -> float`",
+                func_name, module_path, func_name
+            ))
+        })?;
+        if !signature.params.iter().chain(&signature.ret).all(is_scalar) {
+            return Err(FFIError::LoadError(format!(
+                "C function {} can only take and return integers, float, string and bool, not {}",
+                func_name, signature
+            ))
+            .into());
+        }
+        let library = self
+            .libraries
+            .get(module_path)
+            .ok_or_else(|| FFIError::CallError(format!("Library not loaded: {}", module_path)))?;
+
+        let symbol = unsafe { library.get::<*const c_void>(func_name.as_bytes()) }
+            .map_err(|_| FFIError::FunctionNotFound(format!("c.{}", func_path)))?;
+        self.functions
+            .insert(func_path.to_string(), (*symbol as usize, signature.clone()));
+        Ok(true)
+    }
+
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let (address, signature) = self
+            .functions
+            .get(func_path)
+            .ok_or_else(|| FFIError::FunctionNotFound(format!("c.{}", func_path)))?;
+        if !kwargs.is_empty() {
+            return Err(FFIError::CallError(format!(
+                "C function {} takes no named arguments",
+                func_path
+            ))
+            .into());
+        }
+        if args.len() != signature.params.len() {
+            return Err(FFIError::CallError(format!(
+                "C function {} expects {} arguments, but got {}",
+                func_path,
+                signature.params.len(),
+                args.len()
+            ))
+            .into());
+        }
+
+        let values = args
+            .iter()
+            .zip(&signature.params)
+            .map(|(value, ty)| marshal(value, ty))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ffi_args: Vec<Arg> = values
+            .iter()
+            .map(|value| match value {
+                CValue::Int(i) => arg(i),
+                CValue::Long(i) => arg(i),
+                CValue::I64(i) => arg(i),
+                CValue::SizeT(i) => arg(i),
+                CValue::Float(f) => arg(f),
+                CValue::Bool(b) => arg(b),
+                CValue::String(_, ptr) => arg(ptr),
+            })
+            .collect();
+
+        let cif = Cif::new(
+            signature.params.iter().map(ffi_type),
+            signature
+                .ret
+                .as_ref()
+                .map(ffi_type)
+                .unwrap_or_else(libffi::middle::Type::void),
+        );
+        let code = CodePtr::from_ptr(*address as *const c_void);
+
+        // the declared signature is trusted: a wrong one is undefined behaviour, as in C
+        let result = unsafe {
+            match &signature.ret {
+                // small return values are widened to a full register by libffi, so only the
+                // low bits are the C value: a C `int` of -1 is not 4294967295
+                Some(Type::Int) => Primitive::Int(cif.call::<i64>(code, &ffi_args) as c_int as i64),
+                // a `long` is only 32 bits on some platforms
+                #[allow(clippy::unnecessary_cast)]
+                Some(Type::Long) => {
+                    Primitive::Int(cif.call::<i64>(code, &ffi_args) as c_long as i64)
+                }
+                Some(Type::I64) => Primitive::Int(cif.call::<i64>(code, &ffi_args)),
+                Some(Type::SizeT) => {
+                    Primitive::integer(BigInt::from(cif.call::<usize>(code, &ffi_args)))
+                }
+                Some(Type::Float) => Primitive::Float(cif.call::<f64>(code, &ffi_args)),
+                Some(Type::Bool) => Primitive::Bool(cif.call::<u64>(code, &ffi_args) & 0xff != 0),
+                Some(Type::String) => {
+                    let ptr = cif.call::<*const c_char>(code, &ffi_args);
+                    if ptr.is_null() {
+                        Primitive::None
+                    } else {
+                        Primitive::String(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+                    }
+                }
+                None => {
+                    cif.call::<()>(code, &ffi_args);
+                    Primitive::None
+                }
+                Some(_) => unreachable!("C signatures are checked to be scalar when declared"),
+            }
+        };
+        Ok(result)
+    }
+}
@@ -0,0 +1,153 @@
+// ffi/cache.rs
+//
+// results of pure foreign functions, kept on disk across runs. an entry is keyed by a hash
+// of the function, the source of its module and its arguments: after editing a script only
+// the calls whose arguments changed run again, after editing a module all of its calls do.
+//
+//     <dir>/3f/3fa2...c1.json    {"function": "python.llm.complete", "result": "hello"}
+
+use super::json::{kwargs_to_stored_json, primitive_from_stored_json, primitive_to_stored_json};
+use crate::syntax::Primitive;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+pub const GALOIS_CACHE_DIR: &str = "GALOIS_CACHE_DIR";
+
+// part of every key: entries written before non-finite floats were stored whole are not read
+const FORMAT: u32 = 2;
+
+pub struct Cache {
+    dir: PathBuf,
+    sources: RwLock<HashMap<String, String>>, // qualified module -> hash of its source
+    writes: AtomicUsize,                      // keeps temporary file names unique
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache {
+            dir,
+            sources: RwLock::new(HashMap::new()),
+            writes: AtomicUsize::new(0),
+        }
+    }
+
+    /// `GALOIS_CACHE_DIR`, else `galois` in the user's cache directory.
+    pub fn default_dir() -> PathBuf {
+        if let Some(dir) = env::var_os(GALOIS_CACHE_DIR) {
+            return PathBuf::from(dir);
+        }
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(env::temp_dir)
+            .join("galois")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // removes the entries only, never anything else that lives in the directory
+    pub fn clear(&self) -> io::Result<()> {
+        let buckets = match fs::read_dir(&self.dir) {
+            Ok(buckets) => buckets,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for bucket in buckets {
+            let bucket = bucket?.path();
+            let is_bucket = bucket.is_dir()
+                && bucket
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit())
+                    });
+            if !is_bucket {
+                continue;
+            }
+            for entry in fs::read_dir(&bucket)? {
+                let entry = entry?.path();
+                if entry
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    fs::remove_file(entry)?;
+                }
+            }
+            let _ = fs::remove_dir(bucket);
+        }
+        Ok(())
+    }
+
+    // a module whose source cannot be read is identified by its name alone
+    pub fn note_source(&self, module_path: &str, source: &Path) {
+        if let (Ok(content), Ok(mut sources)) = (fs::read(source), self.sources.write()) {
+            sources.insert(module_path.to_string(), hex(&Sha256::digest(content)));
+        }
+    }
+
+    // `function` is fully qualified: <language>.<module>.<function>
+    pub fn key(
+        &self,
+        function: &str,
+        args: &[Primitive],
+        kwargs: &[(String, Primitive)],
+    ) -> String {
+        let module_path = function.rsplit_once('.').map_or("", |(module, _)| module);
+        let source = self
+            .sources
+            .read()
+            .ok()
+            .and_then(|sources| sources.get(module_path).cloned());
+        let identity = json!({
+            "format": FORMAT,
+            "function": function,
+            "source": source,
+            "args": args.iter().map(primitive_to_stored_json).collect::<Vec<_>>(),
+            "kwargs": kwargs_to_stored_json(kwargs),
+        });
+        hex(&Sha256::digest(identity.to_string()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Primitive> {
+        let content = fs::read_to_string(self.entry(key)).ok()?;
+        let entry: serde_json::Value = serde_json::from_str(&content).ok()?;
+        primitive_from_stored_json(&entry["result"]).ok()
+    }
+
+    // written under a temporary name and renamed, so a concurrent reader never sees half an
+    // entry. failing to write only costs a later cache miss.
+    pub fn put(&self, key: &str, function: &str, result: &Primitive) {
+        let entry = self.entry(key);
+        let temporary = entry.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            self.writes.fetch_add(1, Ordering::SeqCst)
+        ));
+        let content = json!({ "function": function, "result": primitive_to_stored_json(result) });
+        let written = entry
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&temporary, content.to_string()))
+            .and_then(|_| fs::rename(&temporary, &entry));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(format!("{}.json", key))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,148 @@
+// ffi/effect.rs
+//
+// what a foreign function does besides returning its result. a pure function does nothing
+// else; a named effect (`print`, `network`) is a channel, and calls on the same channel are
+// not reordered; `io` may do anything, so it is ordered with respect to every effectful call.
+// a function nobody declared an effect for is `io`.
+//
+// a channel can name an argument of the call, `file:{0}` or `file:{path}`, so that writes to
+// one file are ordered and writes to different files are not. when the argument is still
+// being computed the call takes `file:*`, every channel starting with `file:`.
+
+use super::Promise;
+use futures::future::{self, FutureExt};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Pure,
+    Named(BTreeSet<String>),
+    Io,
+}
+
+impl Effect {
+    pub fn named<S: Into<String>>(channels: impl IntoIterator<Item = S>) -> Effect {
+        let channels: BTreeSet<String> = channels.into_iter().map(Into::into).collect();
+        if channels.is_empty() {
+            Effect::Pure
+        } else {
+            Effect::Named(channels)
+        }
+    }
+
+    // the channels of one call, with the arguments named in them filled in. `arg` gives the
+    // value of a positional (`0`) or named argument, if it is known already.
+    pub fn for_call(&self, arg: impl Fn(&str) -> Option<String>) -> Effect {
+        let Effect::Named(channels) = self else {
+            return self.clone();
+        };
+        Effect::named(channels.iter().map(|channel| {
+            let (Some(start), Some(end)) = (channel.find('{'), channel.find('}')) else {
+                return channel.clone();
+            };
+            if end < start {
+                return channel.clone();
+            }
+            match arg(&channel[start + 1..end]) {
+                Some(value) => format!("{}{}{}", &channel[..start], value, &channel[end + 1..]),
+                None => format!("{}*", &channel[..start]),
+            }
+        }))
+    }
+
+    // everything both do
+    pub fn join(self, other: Effect) -> Effect {
+        match (self, other) {
+            (Effect::Io, _) | (_, Effect::Io) => Effect::Io,
+            (Effect::Pure, effect) | (effect, Effect::Pure) => effect,
+            (Effect::Named(mut a), Effect::Named(b)) => {
+                a.extend(b);
+                Effect::Named(a)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::Pure => write!(f, "pure"),
+            Effect::Named(channels) => {
+                write!(
+                    f,
+                    "{}",
+                    channels.iter().cloned().collect::<Vec<_>>().join(", ")
+                )
+            }
+            Effect::Io => write!(f, "io"),
+        }
+    }
+}
+
+// whether two channels of calls can be the same: `file:*` overlaps every `file:` channel
+fn overlap(a: &str, b: &str) -> bool {
+    let covers = |wildcard: &str, channel: &str| {
+        wildcard
+            .strip_suffix('*')
+            .is_some_and(|prefix| channel.starts_with(prefix))
+    };
+    a == b || covers(a, b) || covers(b, a)
+}
+
+// the last effectful calls started under `--parallel`, which the next ones wait for
+#[derive(Default)]
+pub struct Channels {
+    io: Option<Promise>,
+    named: HashMap<String, Promise>,
+}
+
+impl Channels {
+    // the calls a new call with `effect` must wait for, registering `call` as the one the
+    // calls after it wait for. a failed call counts as done.
+    pub fn order(
+        &mut self,
+        effect: &Effect,
+        call: impl FnOnce(Vec<future::BoxFuture<'static, ()>>) -> Promise,
+    ) -> Promise {
+        let done = |promise: &Promise| promise.clone().map(|_| ()).boxed();
+        match effect {
+            Effect::Pure => call(Vec::new()),
+            Effect::Named(channels) => {
+                let before = self
+                    .io
+                    .iter()
+                    .chain(
+                        self.named
+                            .iter()
+                            .filter(|(last, _)| channels.iter().any(|c| overlap(c, last)))
+                            .map(|(_, promise)| promise),
+                    )
+                    .map(done)
+                    .collect();
+                let promise = call(before);
+                // a wildcard call waited for every channel it covers, so it stands in for them
+                for wildcard in channels.iter().filter(|c| c.ends_with('*')) {
+                    self.named.retain(|last, _| !overlap(wildcard, last));
+                }
+                for channel in channels {
+                    self.named.insert(channel.clone(), promise.clone());
+                }
+                promise
+            }
+            // waits for every channel, so the calls after it need only wait for it
+            Effect::Io => {
+                let before = self
+                    .io
+                    .iter()
+                    .chain(self.named.values())
+                    .map(done)
+                    .collect();
+                let promise = call(before);
+                self.named.clear();
+                self.io = Some(promise.clone());
+                promise
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// ffi/json.rs
+
+use super::FFIError;
+use crate::syntax::Primitive;
+use num_bigint::BigInt;
+use serde_json::{json, Map, Number};
+
+// what the cache and the record log keep must come back exactly, but json has no number for nan
+// or infinity: those are stored as `{"$float": "NaN"}`, and a record with a `$` key is wrapped in
+// `{"$record": {...}}` so that it is not taken for one
+const STORED_FLOAT: &str = "$float";
+const STORED_RECORD: &str = "$record";
+
+pub fn primitive_to_json(primitive: &Primitive) -> serde_json::Value {
+    to_json(primitive, false)
+}
+
+pub fn primitive_to_stored_json(primitive: &Primitive) -> serde_json::Value {
+    to_json(primitive, true)
+}
+
+pub fn kwargs_to_stored_json(kwargs: &[(String, Primitive)]) -> serde_json::Value {
+    serde_json::Value::Object(
+        kwargs
+            .iter()
+            .map(|(name, value)| (name.clone(), primitive_to_stored_json(value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+fn to_json(primitive: &Primitive, stored: bool) -> serde_json::Value {
+    match primitive {
+        Primitive::Int(i) => json!(i),
+        Primitive::BigInt(i) => {
+            serde_json::Value::Number(i.to_string().parse().expect("an integer is a json number"))
+        }
+        Primitive::Float(f) => match Number::from_f64(*f) {
+            Some(n) => serde_json::Value::Number(n),
+            None if stored => json!({ STORED_FLOAT: f.to_string() }),
+            None => serde_json::Value::Null,
+        },
+        Primitive::String(s) => json!(s),
+        Primitive::Bool(b) => json!(b),
+        Primitive::None => serde_json::Value::Null,
+        Primitive::Array(arr) => arr.iter().map(|p| to_json(p, stored)).collect(),
+        Primitive::Record(fields) => {
+            let object = serde_json::Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), to_json(value, stored)))
+                    .collect(),
+            );
+            if stored && fields.keys().any(|name| name.starts_with('$')) {
+                json!({ STORED_RECORD: object })
+            } else {
+                object
+            }
+        }
+    }
+}
+
+pub fn primitive_from_json(json: &serde_json::Value) -> Result<Primitive, FFIError> {
+    from_json(json, false)
+}
+
+pub fn primitive_from_stored_json(json: &serde_json::Value) -> Result<Primitive, FFIError> {
+    from_json(json, true)
+}
+
+fn from_json(json: &serde_json::Value, stored: bool) -> Result<Primitive, FFIError> {
+    match json {
+        serde_json::Value::Null => Ok(Primitive::None),
+        serde_json::Value::Bool(b) => Ok(Primitive::Bool(*b)),
+        // numbers are kept as written, so integers of any size come through whole
+        serde_json::Value::Number(n) => match (n.as_i64(), n.to_string().parse::<BigInt>()) {
+            (Some(i), _) => Ok(Primitive::Int(i)),
+            (None, Ok(i)) => Ok(Primitive::integer(i)),
+            (None, Err(_)) => n
+                .as_f64()
+                .map(Primitive::Float)
+                .ok_or_else(|| FFIError::CallError(format!("Unsupported number: {}", n))),
+        },
+        serde_json::Value::String(s) => Ok(Primitive::String(s.clone())),
+        serde_json::Value::Array(arr) => arr
+            .iter()
+            .map(|json| from_json(json, stored))
+            .collect::<Result<_, _>>()
+            .map(Primitive::Array),
+        serde_json::Value::Object(fields) => {
+            let record = |fields: &Map<String, serde_json::Value>| {
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), from_json(value, stored)?)))
+                    .collect::<Result<_, _>>()
+                    .map(Primitive::Record)
+            };
+            match fields.iter().next() {
+                Some((tag, serde_json::Value::String(f)))
+                    if stored && fields.len() == 1 && tag == STORED_FLOAT =>
+                {
+                    f.parse()
+                        .map(Primitive::Float)
+                        .map_err(|_| FFIError::CallError(format!("Unsupported float: {}", f)))
+                }
+                Some((tag, serde_json::Value::Object(wrapped)))
+                    if stored && fields.len() == 1 && tag == STORED_RECORD =>
+                {
+                    record(wrapped)
+                }
+                _ => record(fields),
+            }
+        }
+    }
+}
+
+pub fn kwargs_to_json(kwargs: &[(String, Primitive)]) -> serde_json::Value {
+    serde_json::Value::Object(
+        kwargs
+            .iter()
+            .map(|(name, value)| (name.clone(), primitive_to_json(value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn stored(primitive: &Primitive) -> String {
+        let json = primitive_to_stored_json(primitive);
+        let back = primitive_from_stored_json(&json).expect("stored json reads back");
+        format!("{} -> {:?}", json, back)
+    }
+
+    #[test]
+    fn non_finite_floats_are_kept() {
+        assert_eq!(
+            stored(&Primitive::Float(f64::NAN)),
+            r#"{"$float":"NaN"} -> Float(NaN)"#
+        );
+        assert_eq!(
+            stored(&Primitive::Array(vec![
+                Primitive::Float(f64::INFINITY),
+                Primitive::Float(f64::NEG_INFINITY),
+                Primitive::None,
+            ])),
+            r#"[{"$float":"inf"},{"$float":"-inf"},null] -> Array([Float(inf), Float(-inf), None])"#
+        );
+        assert_eq!(stored(&Primitive::Float(1.5)), "1.5 -> Float(1.5)");
+        // what a backend is sent is unchanged
+        assert_eq!(
+            primitive_to_json(&Primitive::Float(f64::NAN)),
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn records_that_look_like_tags_are_kept() {
+        let record = Primitive::Record(BTreeMap::from([(
+            "$float".to_string(),
+            Primitive::String("NaN".to_string()),
+        )]));
+        assert_eq!(
+            stored(&record),
+            r#"{"$record":{"$float":"NaN"}} -> Record({"$float": String("NaN")})"#
+        );
+        let plain = Primitive::Record(BTreeMap::from([("a".to_string(), Primitive::Int(1))]));
+        assert_eq!(stored(&plain), r#"{"a":1} -> Record({"a": Int(1)})"#);
+    }
+}
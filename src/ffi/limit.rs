@@ -0,0 +1,286 @@
+// ffi/limit.rs
+//
+// limits on how a foreign module or function is called, from `#[limit(...)]` on its
+// declaration or from a `--limits` file:
+//
+//     #[limit(concurrency = 4, rate = 2.0, timeout = 30.0, retries = 3, retry_on = ["TimeoutError"])]
+//     from python.llm use *
+//
+// `concurrency` caps the calls in flight, `rate` the calls started per second (a token bucket
+// holding up to `burst` tokens). a call running longer than `timeout` seconds fails with a
+// `TimeoutError`; a call failing with one of the `retry_on` kinds is tried again up to
+// `retries` times, waiting `backoff` seconds before the first retry and twice as long before
+// each next one. a call made under both a function's and its module's limits obeys both.
+
+use super::json::primitive_from_json;
+use super::{FFIError, ForeignException};
+use crate::syntax::Primitive;
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_RETRIES: u32 = 3; // when only `retry_on` is given
+
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub concurrency: Option<usize>,
+    pub rate: Option<f64>, // calls per second
+    pub burst: Option<f64>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub retry_on: Vec<String>, // error kinds worth another try
+    pub backoff: Option<Duration>,
+}
+
+impl Limits {
+    pub fn parse(target: &str, options: &[(String, Primitive)]) -> Result<Limits, FFIError> {
+        let invalid = |reason: String| {
+            FFIError::LoadError(format!("Invalid limits for {}: {}", target, reason))
+        };
+        let positive = |name: &str, value: &Primitive| match value {
+            Primitive::Int(i) if *i > 0 => Ok(*i as f64),
+            Primitive::Float(f) if *f > 0.0 => Ok(*f),
+            other => Err(invalid(format!(
+                "`{}` must be a positive number, got {}",
+                name, other
+            ))),
+        };
+        let count = |name: &str, value: &Primitive| match value {
+            Primitive::Int(i) if *i >= 0 => Ok(*i as u64),
+            other => Err(invalid(format!(
+                "`{}` must be a non-negative integer, got {}",
+                name, other
+            ))),
+        };
+        let duration = |name: &str, value: &Primitive| {
+            let seconds = positive(name, value)?;
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|_| invalid(format!("`{}` is too long, got {:e} seconds", name, seconds)))
+        };
+
+        let mut limits = Limits::default();
+        for (name, value) in options {
+            match name.as_str() {
+                "concurrency" => match count(name, value)? {
+                    0 => return Err(invalid("`concurrency` must be at least 1".to_string())),
+                    n if n > Semaphore::MAX_PERMITS as u64 => {
+                        return Err(invalid(format!(
+                            "`concurrency` must be at most {}, got {}",
+                            Semaphore::MAX_PERMITS,
+                            n
+                        )));
+                    }
+                    n => limits.concurrency = Some(n as usize),
+                },
+                "rate" => {
+                    let rate = positive(name, value)?;
+                    // a token must come back in a time that can be waited for
+                    if Duration::try_from_secs_f64(1.0 / rate).is_err() {
+                        return Err(invalid(format!("`rate` is too low, got {:e}", rate)));
+                    }
+                    limits.rate = Some(rate);
+                }
+                "burst" => limits.burst = Some(positive(name, value)?),
+                "timeout" => limits.timeout = Some(duration(name, value)?),
+                "retries" => {
+                    let retries = count(name, value)?;
+                    limits.retries = Some(u32::try_from(retries).map_err(|_| {
+                        invalid(format!(
+                            "`retries` must be at most {}, got {}",
+                            u32::MAX,
+                            retries
+                        ))
+                    })?);
+                }
+                "backoff" => limits.backoff = Some(duration(name, value)?),
+                "retry_on" => {
+                    limits.retry_on = match value {
+                        Primitive::String(kind) => vec![kind.clone()],
+                        Primitive::Array(kinds) => kinds
+                            .iter()
+                            .map(|kind| match kind {
+                                Primitive::String(kind) => Ok(kind.clone()),
+                                other => Err(invalid(format!(
+                                    "`retry_on` lists error kinds, got {}",
+                                    other
+                                ))),
+                            })
+                            .collect::<Result<_, _>>()?,
+                        other => {
+                            return Err(invalid(format!(
+                                "`retry_on` lists error kinds, got {}",
+                                other
+                            )));
+                        }
+                    }
+                }
+                _ => return Err(invalid(format!("unknown limit `{}`", name))),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+// `{"python.llm": {"concurrency": 4, "retry_on": ["TimeoutError"]}, ...}`
+pub fn load_limits(path: &Path) -> Result<Vec<(String, Limits)>, FFIError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+    let json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+    let targets = json.as_object().ok_or_else(|| {
+        FFIError::LoadError(format!(
+            "{}: limits must map modules and functions to their limits",
+            path.display()
+        ))
+    })?;
+
+    targets
+        .iter()
+        .map(|(target, options)| {
+            let options = options
+                .as_object()
+                .ok_or_else(|| {
+                    FFIError::LoadError(format!(
+                        "Invalid limits for {}: expected an object",
+                        target
+                    ))
+                })?
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), primitive_from_json(value)?)))
+                .collect::<Result<Vec<_>, FFIError>>()?;
+            Ok((target.clone(), Limits::parse(target, &options)?))
+        })
+        .collect()
+}
+
+// tokens go negative when calls queue up: each one waits until its token has refilled
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct Limiter {
+    limits: Limits,
+    burst: f64,
+    in_flight: Option<Semaphore>,
+    bucket: Mutex<Bucket>,
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        let burst = limits
+            .burst
+            .or(limits.rate.map(|rate| rate.ceil()))
+            .unwrap_or(1.0)
+            .max(1.0);
+        Limiter {
+            in_flight: limits.concurrency.map(Semaphore::new),
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+            burst,
+            limits,
+        }
+    }
+
+    // takes a token and says how long to wait before it may be used
+    fn reserve(&self) -> Duration {
+        let (Some(rate), Ok(mut bucket)) = (self.limits.rate, self.bucket.lock()) else {
+            return Duration::ZERO;
+        };
+        let now = Instant::now();
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst) - 1.0;
+        bucket.updated = now;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            // many calls queued behind a slow rate may wait longer than a `Duration` holds
+            Duration::try_from_secs_f64(-bucket.tokens / rate).unwrap_or(Duration::MAX)
+        }
+    }
+
+    async fn admit(&self) -> Result<Option<SemaphorePermit<'_>>, FFIError> {
+        let permit = match &self.in_flight {
+            Some(in_flight) => Some(
+                in_flight
+                    .acquire()
+                    .await
+                    .map_err(|e| FFIError::CallError(e.to_string()))?,
+            ),
+            None => None,
+        };
+        tokio::time::sleep(self.reserve()).await;
+        Ok(permit)
+    }
+}
+
+// `limiters` are the most specific first: the function's, then its module's. timeouts and
+// retries come from the first that sets them, concurrency and rate from every one of them.
+pub async fn call_limited<F, Fut>(
+    function: &str,
+    limiters: &[Arc<Limiter>],
+    mut call: F,
+) -> Result<Primitive, FFIError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Primitive, FFIError>>,
+{
+    let setting = |get: fn(&Limits) -> Option<Duration>| {
+        limiters.iter().find_map(|limiter| get(&limiter.limits))
+    };
+    let timeout = setting(|limits| limits.timeout);
+    let backoff = setting(|limits| limits.backoff).unwrap_or(DEFAULT_BACKOFF);
+    let retry_on: &[String] = limiters
+        .iter()
+        .map(|limiter| &limiter.limits.retry_on)
+        .find(|retry_on| !retry_on.is_empty())
+        .map_or(&[], |retry_on| retry_on.as_slice());
+    let retries = limiters
+        .iter()
+        .find_map(|limiter| limiter.limits.retries)
+        .unwrap_or(if retry_on.is_empty() {
+            0
+        } else {
+            DEFAULT_RETRIES
+        });
+
+    let mut attempt = 0;
+    loop {
+        let mut permits = Vec::new();
+        for limiter in limiters {
+            permits.push(limiter.admit().await?);
+        }
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call())
+                .await
+                .unwrap_or_else(|_| {
+                    Err(FFIError::Exception(ForeignException::new(
+                        "TimeoutError",
+                        &format!(
+                            "{} did not finish within {}s",
+                            function,
+                            timeout.as_secs_f64()
+                        ),
+                    )))
+                }),
+            None => call().await,
+        };
+        drop(permits);
+
+        match result {
+            Err(FFIError::Exception(exception))
+                if attempt < retries && retry_on.iter().any(|kind| exception.is_a(kind)) =>
+            {
+                tokio::time::sleep(backoff * 2u32.saturating_pow(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,290 @@
+// ffi/mock.rs
+//
+// canned foreign functions for testing scripts, read from a JSON fixture file and standing in
+// for every real backend:
+//
+//     {
+//       "python.llm.complete": [
+//         { "args": ["hello"], "result": "hi there", "times": 1 },
+//         { "args": [{ "any": true }], "kwargs": { "model": "small" }, "result": "ok" },
+//         { "error": { "kind": "TimeoutError", "message": "too slow" } }
+//       ]
+//     }
+//
+// rules are tried in order and the first matching one answers. a missing `args` or `kwargs`
+// matches anything, `{ "any": true }` matches any single argument. a rule with `times` answers
+// at most that many calls and must have answered exactly that many by the end of the run.
+
+use super::json::{primitive_from_json, primitive_to_json};
+use super::{FFIError, FFIProtocol, ForeignException};
+use crate::syntax::{Primitive, Signature};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+enum Pattern {
+    Any,
+    Exact(serde_json::Value),
+}
+
+impl Pattern {
+    fn parse(json: &serde_json::Value) -> Pattern {
+        match json.get("any") {
+            Some(serde_json::Value::Bool(true)) => Pattern::Any,
+            _ => Pattern::Exact(json.clone()),
+        }
+    }
+
+    fn matches(&self, value: &Primitive) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::Exact(expected) => *expected == primitive_to_json(value),
+        }
+    }
+}
+
+struct Rule {
+    args: Option<Vec<Pattern>>,
+    kwargs: Option<Vec<(String, Pattern)>>,
+    response: Result<Primitive, ForeignException>,
+    times: Option<usize>,
+    calls: AtomicUsize,
+}
+
+impl Rule {
+    fn parse(function: &str, json: &serde_json::Value) -> Result<Rule, FFIError> {
+        let invalid = |reason: &str| {
+            FFIError::LoadError(format!("Invalid fixture for {}: {}", function, reason))
+        };
+
+        let args = match json.get("args") {
+            None => None,
+            Some(serde_json::Value::Array(args)) => Some(args.iter().map(Pattern::parse).collect()),
+            Some(_) => return Err(invalid("`args` must be an array")),
+        };
+        let kwargs = match json.get("kwargs") {
+            None => None,
+            Some(serde_json::Value::Object(kwargs)) => Some(
+                kwargs
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Pattern::parse(value)))
+                    .collect(),
+            ),
+            Some(_) => return Err(invalid("`kwargs` must be an object")),
+        };
+        let response = match (json.get("result"), json.get("error")) {
+            (Some(result), None) => Ok(
+                primitive_from_json(result).map_err(|e| invalid(&format!("`result`: {}", e)))?
+            ),
+            (None, Some(error)) => Err(ForeignException::new(
+                error["kind"].as_str().unwrap_or("MockError"),
+                error["message"].as_str().unwrap_or_default(),
+            )),
+            (None, None) => Ok(Primitive::None),
+            (Some(_), Some(_)) => return Err(invalid("a rule has either `result` or `error`")),
+        };
+        let times = match json.get("times") {
+            None => None,
+            Some(times) => Some(
+                times
+                    .as_u64()
+                    .ok_or_else(|| invalid("`times` must be a non-negative integer"))?
+                    as usize,
+            ),
+        };
+
+        Ok(Rule {
+            args,
+            kwargs,
+            response,
+            times,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    fn matches(&self, args: &[Primitive], kwargs: &[(String, Primitive)]) -> bool {
+        let args_match = self.args.as_ref().is_none_or(|patterns| {
+            patterns.len() == args.len() && patterns.iter().zip(args).all(|(p, arg)| p.matches(arg))
+        });
+        let kwargs_match = self.kwargs.as_ref().is_none_or(|patterns| {
+            patterns.len() == kwargs.len()
+                && patterns.iter().all(|(name, pattern)| {
+                    kwargs
+                        .iter()
+                        .any(|(arg_name, arg)| arg_name == name && pattern.matches(arg))
+                })
+        });
+        args_match && kwargs_match
+    }
+
+    // claims one of the rule's remaining calls
+    fn take(&self) -> bool {
+        match self.times {
+            None => {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            Some(times) => self
+                .calls
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |calls| {
+                    (calls < times).then_some(calls + 1)
+                })
+                .is_ok(),
+        }
+    }
+}
+
+// fully qualified function -> its rules, shared by the mock of every language
+pub struct Fixtures {
+    functions: BTreeMap<String, Vec<Rule>>,
+}
+
+impl Fixtures {
+    pub fn load(path: &Path) -> Result<Fixtures, FFIError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+        let json: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+        Fixtures::from_json(&json)
+    }
+
+    pub fn from_json(json: &serde_json::Value) -> Result<Fixtures, FFIError> {
+        let functions = json.as_object().ok_or_else(|| {
+            FFIError::LoadError("Fixtures must map function names to rules".to_string())
+        })?;
+
+        let mut parsed = BTreeMap::new();
+        for (function, rules) in functions {
+            let rules = match rules {
+                serde_json::Value::Array(rules) => rules
+                    .iter()
+                    .map(|rule| Rule::parse(function, rule))
+                    .collect::<Result<Vec<_>, _>>()?,
+                rule => vec![Rule::parse(function, rule)?],
+            };
+            parsed.insert(function.clone(), rules);
+        }
+        Ok(Fixtures { functions: parsed })
+    }
+
+    // every rule with `times` has answered exactly that many calls
+    pub fn check_expectations(&self) -> Result<(), FFIError> {
+        let unmet: Vec<String> = self
+            .functions
+            .iter()
+            .flat_map(|(function, rules)| {
+                rules.iter().enumerate().filter_map(move |(i, rule)| {
+                    let calls = rule.calls.load(Ordering::SeqCst);
+                    rule.times.filter(|times| *times != calls).map(|times| {
+                        format!(
+                            "{} (rule {}): expected {} calls, got {}",
+                            function,
+                            i + 1,
+                            times,
+                            calls
+                        )
+                    })
+                })
+            })
+            .collect();
+
+        if unmet.is_empty() {
+            Ok(())
+        } else {
+            Err(FFIError::CallError(format!(
+                "Mock expectations not met:\n  {}",
+                unmet.join("\n  ")
+            )))
+        }
+    }
+}
+
+pub struct MockFFI {
+    language: String,
+    fixtures: Arc<Fixtures>,
+}
+
+impl MockFFI {
+    pub fn new(language: &str, fixtures: Arc<Fixtures>) -> Self {
+        MockFFI {
+            language: language.to_string(),
+            fixtures,
+        }
+    }
+}
+
+impl FFIProtocol for MockFFI {
+    fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let prefix = format!("{}.{}.", self.language, module_path);
+        let functions: Vec<String> = self
+            .fixtures
+            .functions
+            .keys()
+            .filter_map(|function| function.strip_prefix(&prefix))
+            .filter(|function| !function.contains('.'))
+            .map(String::from)
+            .collect();
+
+        if functions.is_empty() {
+            return Err(FFIError::ModuleNotFound(
+                format!("{}.{} (no fixtures)", self.language, module_path),
+                Vec::new(),
+            )
+            .into());
+        }
+        Ok(functions)
+    }
+
+    // a signature changes nothing: the fixture defines the function
+    fn declare(
+        &mut self,
+        func_path: &str,
+        _signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let function = format!("{}.{}", self.language, func_path);
+        if self.fixtures.functions.contains_key(&function) {
+            Ok(true)
+        } else {
+            Err(FFIError::FunctionNotFound(function).into())
+        }
+    }
+
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let function = format!("{}.{}", self.language, func_path);
+        let rules = self
+            .fixtures
+            .functions
+            .get(&function)
+            .ok_or_else(|| FFIError::FunctionNotFound(function.clone()))?;
+
+        let rule = rules
+            .iter()
+            .find(|rule| rule.matches(&args, &kwargs) && rule.take())
+            .ok_or_else(|| {
+                let mut call: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                call.extend(
+                    kwargs
+                        .iter()
+                        .map(|(name, value)| format!("{} = {}", name, value)),
+                );
+                FFIError::CallError(format!(
+                    "No fixture matches {}({})",
+                    function,
+                    call.join(", ")
+                ))
+            })?;
+
+        match &rule.response {
+            Ok(result) => Ok(result.clone()),
+            Err(exception) => Err(FFIError::Exception(exception.clone()).into()),
+        }
+    }
+}
@@ -1,96 +1,692 @@
+pub mod batch;
+pub mod c;
+pub mod cache;
+pub mod effect;
+pub mod json;
+pub mod limit;
+pub mod mock;
+pub mod ownership;
+pub mod proc;
 pub mod python;
+pub mod record;
+pub mod schedule;
+pub mod search_path;
+pub mod sys;
 
+use crate::ffi::batch::{Batcher, Batching};
+use crate::ffi::c::NativeFFI;
+use crate::ffi::cache::Cache;
+use crate::ffi::effect::{Channels, Effect};
+use crate::ffi::limit::{call_limited, Limiter, Limits};
+use crate::ffi::mock::{Fixtures, MockFFI};
+use crate::ffi::ownership::Ownership;
+use crate::ffi::proc::ProcFFI;
 use crate::ffi::python::PythonFFI;
-use crate::syntax::Value;
-use std::collections::HashMap;
+use crate::ffi::record::{Recorder, Recording, ReplayFFI};
+use crate::ffi::schedule::Schedule;
+use crate::ffi::search_path::SearchPath;
+use crate::ffi::sys::SysFFI;
+use crate::syntax::{Primitive, Signature};
+use futures::future::{self, BoxFuture, Shared};
+use futures::{FutureExt, TryFutureExt};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::runtime::Runtime;
 
-pub trait FFIProtocol {
+// backends are shared with the worker threads of `--parallel` runs, so calls take and return
+// primitives only
+pub trait FFIProtocol: Send + Sync {
     fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>>;
-    fn call_function(&self, func_name: &str, args: Vec<Value>) -> Result<Value, Box<dyn Error>>;
+    // called for every `from ... use` of a single function; returns true when the declaration
+    // itself defines the function (e.g. a C symbol with its signature)
+    fn declare(
+        &mut self,
+        _func_path: &str,
+        _signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+    // the file a module was loaded from; cached results of its functions are dropped when it
+    // changes
+    fn source(&self, _module_path: &str) -> Option<PathBuf> {
+        None
+    }
+    // what a function says it does; see effect.rs for what is assumed otherwise
+    fn effect(&self, _func_path: &str) -> Option<Effect> {
+        None
+    }
+    // whether what a function returns may be used only once (see ownership.rs)
+    fn ownership(&self, _func_path: &str) -> Option<Ownership> {
+        None
+    }
+    // a function that declares itself batchable takes a list of argument lists and returns a
+    // list of results (see batch.rs)
+    fn batching(&self, _func_path: &str) -> Option<Batching> {
+        None
+    }
+    fn call_function(
+        &self,
+        func_name: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>>;
+    // used by `--parallel`: a backend whose calls can finish later without holding a thread
+    // (python coroutines) hands back the rest of the call as a future
+    fn begin_call(
+        &self,
+        func_name: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<CallResult, Box<dyn Error>> {
+        self.call_function(func_name, args, kwargs)
+            .map(CallResult::Ready)
+    }
+}
+
+pub enum CallResult {
+    Ready(Primitive),
+    Awaiting(BoxFuture<'static, Result<Primitive, FFIError>>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum FFIError {
-    ModuleNotFound(String),
+    ModuleNotFound(String, Vec<PathBuf>),
     ProtocolNotImplemented(String),
     FunctionNotFound(String),
-    AmbiguousFunction(String),
+    AmbiguousFunction(String, Vec<String>),
     LoadError(String),
     CallError(String),
+    Exception(ForeignException),
+    TypeMismatch(String), // a value breaking the declared signature of a function
+}
+
+// an exception raised by foreign code, kept structured so scripts can match on its kind
+#[derive(Debug, Clone)]
+pub struct ForeignException {
+    pub kind: String,
+    pub bases: Vec<String>, // base classes, most specific first
+    pub message: String,
+    pub traceback: Option<String>,
+}
+
+impl ForeignException {
+    pub fn new(kind: &str, message: &str) -> Self {
+        ForeignException {
+            kind: kind.to_string(),
+            bases: Vec::new(),
+            message: message.to_string(),
+            traceback: None,
+        }
+    }
+
+    pub fn is_a(&self, kind: &str) -> bool {
+        self.kind == kind || self.bases.iter().any(|base| base == kind)
+    }
+}
+
+impl fmt::Display for ForeignException {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.kind, self.message)
+        }
+    }
 }
 
 impl fmt::Display for FFIError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            FFIError::ModuleNotFound(name) => write!(f, "Module not found: {}", name),
+            FFIError::ModuleNotFound(name, searched) => {
+                write!(f, "Module not found: {}", name)?;
+                if !searched.is_empty() {
+                    write!(f, "\n  searched:")?;
+                    for path in searched {
+                        write!(f, "\n    {}", path.display())?;
+                    }
+                }
+                Ok(())
+            }
             FFIError::ProtocolNotImplemented(name) => {
                 write!(f, "FFI protocol not implemented for: {}", name)
             }
             FFIError::FunctionNotFound(name) => write!(f, "Function not found: {}", name),
-            FFIError::AmbiguousFunction(name) => write!(f, "Ambiguous function call: {}", name),
+            FFIError::AmbiguousFunction(name, candidates) => write!(
+                f,
+                "Ambiguous function call: {} (provided by {})",
+                name,
+                candidates.join(", ")
+            ),
             FFIError::LoadError(msg) => write!(f, "Error loading module: {}", msg),
             FFIError::CallError(msg) => write!(f, "Error calling function: {}", msg),
+            FFIError::Exception(exception) => write!(f, "{}", exception),
+            FFIError::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
         }
     }
 }
 
 impl Error for FFIError {}
 
+impl From<Box<dyn Error>> for FFIError {
+    fn from(error: Box<dyn Error>) -> Self {
+        match error.downcast::<FFIError>() {
+            Ok(ffi_error) => *ffi_error,
+            Err(error) => FFIError::CallError(error.to_string()),
+        }
+    }
+}
+
+// the eventual result of a call running on a worker thread. it is shared: every use of the
+// result waits on the same call.
+pub type Promise = Shared<BoxFuture<'static, Result<Primitive, FFIError>>>;
+
+pub fn resolved(value: Primitive) -> Promise {
+    future::ready(Ok(value)).boxed().shared()
+}
+
+fn failed(error: FFIError) -> Promise {
+    future::ready(Err(error)).boxed().shared()
+}
+
+type SharedProtocol = Arc<RwLock<Box<dyn FFIProtocol>>>;
+
+// one call of a backend, on the blocking pool of the worker runtime; a coroutine it returns is
+// awaited without holding a thread
+async fn invoke(
+    protocol: SharedProtocol,
+    function: String,
+    args: Vec<Primitive>,
+    kwargs: Vec<(String, Primitive)>,
+) -> Result<Primitive, FFIError> {
+    let started = tokio::task::spawn_blocking(move || {
+        let (language, func_path) = function
+            .split_once('.')
+            .ok_or_else(|| FFIError::FunctionNotFound(function.clone()))?;
+        protocol
+            .read()
+            .map_err(|_| FFIError::CallError(format!("Backend poisoned: {}", language)))?
+            .begin_call(func_path, args, kwargs)
+            .map_err(FFIError::from)
+    })
+    .await
+    .map_err(|e| FFIError::CallError(e.to_string()))?;
+    match started? {
+        CallResult::Ready(value) => Ok(value),
+        CallResult::Awaiting(rest) => rest.await,
+    }
+}
+
 pub struct FFIBackend {
-    pub modules: HashMap<String, Box<dyn FFIProtocol>>,
-    pub function_to_module: HashMap<String, (String, String)>, // function -> (language, module)
+    pub modules: HashMap<String, SharedProtocol>, // language -> protocol
+    pub exports: HashMap<String, Vec<String>>,    // qualified module -> public functions
+    pub search_path: SearchPath,
+    runtime: Option<Runtime>,       // only started for `--parallel`
+    mock: Option<Arc<Fixtures>>,    // stands in for every language when set
+    replay: Option<Arc<Recording>>, // likewise
+    recorder: Option<Arc<Recorder>>,
+    cache: Option<Arc<Cache>>,
+    effects: HashMap<String, Effect>, // declared by scripts for a function or module
+    own_effects: HashMap<String, Effect>, // declared by backends for their functions
+    ownership: HashMap<String, Ownership>, // of what functions return, declared by scripts
+    own_ownership: HashMap<String, Ownership>, // likewise, declared by backends
+    channels: Mutex<Channels>,
+    schedule: Option<Mutex<Schedule>>, // random delays for calls, from `--schedule-seed`
+    batchers: HashMap<String, Arc<Batcher>>, // batchable function -> its pending calls
+    limiters: HashMap<String, Arc<Limiter>>, // function or module -> its limits
+    configured: HashSet<String>,       // limited by `--limits`, whatever scripts say
 }
 
 impl FFIBackend {
-    pub fn new() -> Self {
+    pub fn new(search_path: SearchPath) -> Self {
         FFIBackend {
             modules: HashMap::new(),
-            function_to_module: HashMap::new(),
+            exports: HashMap::new(),
+            search_path,
+            runtime: None,
+            mock: None,
+            replay: None,
+            recorder: None,
+            cache: None,
+            effects: HashMap::new(),
+            own_effects: HashMap::new(),
+            ownership: HashMap::new(),
+            own_ownership: HashMap::new(),
+            channels: Mutex::new(Channels::default()),
+            schedule: None,
+            batchers: HashMap::new(),
+            limiters: HashMap::new(),
+            configured: HashSet::new(),
+        }
+    }
+
+    // must be called before any module is loaded
+    pub fn use_cache(&mut self, cache: Cache) {
+        self.cache = Some(Arc::new(cache));
+    }
+
+    pub fn shuffle_schedule(&mut self, seed: u64) {
+        self.schedule = Some(Mutex::new(Schedule::new(seed)));
+    }
+
+    // `target` is a fully qualified function or module
+    pub fn declare_effect(&mut self, target: &str, effect: Effect) {
+        self.effects.insert(target.to_string(), effect);
+    }
+
+    // what the script declared for the function, else for its module, else what the backend
+    // says; `io` when nobody said anything
+    pub fn effect_of(&self, function: &str) -> Effect {
+        let module_path = function
+            .rsplit_once('.')
+            .map(|(module_path, _)| module_path);
+        self.effects
+            .get(function)
+            .or_else(|| module_path.and_then(|module_path| self.effects.get(module_path)))
+            .or_else(|| self.own_effects.get(function))
+            .cloned()
+            .unwrap_or(Effect::Io)
+    }
+
+    // `target` is a fully qualified function or module
+    pub fn declare_ownership(&mut self, target: &str, ownership: Ownership) {
+        self.ownership.insert(target.to_string(), ownership);
+    }
+
+    // what the script declared for the function, else for its module, else what the backend
+    // says; an ordinary value when nobody said anything
+    pub fn ownership_of(&self, function: &str) -> Option<Ownership> {
+        let module_path = function
+            .rsplit_once('.')
+            .map(|(module_path, _)| module_path);
+        self.ownership
+            .get(function)
+            .or_else(|| module_path.and_then(|module_path| self.ownership.get(module_path)))
+            .or_else(|| self.own_ownership.get(function))
+            .copied()
+    }
+
+    pub fn is_pure(&self, function: &str) -> bool {
+        self.effect_of(function) == Effect::Pure
+    }
+
+    // `target` is a fully qualified function or module. limits are enforced on the worker
+    // runtime, so setting any starts it.
+    pub fn limit(&mut self, target: &str, limits: Limits) -> Result<(), Box<dyn Error>> {
+        if !self.configured.contains(target) {
+            self.start_workers()?;
+            self.limiters
+                .insert(target.to_string(), Arc::new(Limiter::new(limits)));
+        }
+        Ok(())
+    }
+
+    // limits from `--limits` replace those declared for the same target
+    pub fn configure_limits(&mut self, target: &str, limits: Limits) -> Result<(), Box<dyn Error>> {
+        self.configured.remove(target);
+        self.limit(target, limits)?;
+        self.configured.insert(target.to_string());
+        Ok(())
+    }
+
+    // the function's own limits first, then its module's
+    fn limiters_for(&self, function: &str) -> Vec<Arc<Limiter>> {
+        let module_path = function
+            .rsplit_once('.')
+            .map(|(module_path, _)| module_path);
+        [Some(function), module_path]
+            .into_iter()
+            .flatten()
+            .filter_map(|target| self.limiters.get(target).cloned())
+            .collect()
+    }
+
+    // mocked and replayed runs neither read nor fill the cache
+    fn cache_for(&self, function: &str) -> Option<Arc<Cache>> {
+        self.cache
+            .as_ref()
+            .filter(|_| self.mock.is_none() && self.replay.is_none() && self.is_pure(function))
+            .cloned()
+    }
+
+    pub fn record_to(&mut self, recorder: Recorder) {
+        self.recorder = Some(Arc::new(recorder));
+    }
+
+    // must be called before any module is loaded
+    pub fn replay_from(&mut self, recording: Recording) {
+        self.replay = Some(Arc::new(recording));
+    }
+
+    // must be called before any module is loaded
+    pub fn use_mock(&mut self, fixtures: Fixtures) {
+        self.mock = Some(Arc::new(fixtures));
+    }
+
+    pub fn check_expectations(&self) -> Result<(), FFIError> {
+        match &self.mock {
+            Some(fixtures) => fixtures.check_expectations(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn start_workers(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.runtime.is_none() {
+            self.runtime = Some(
+                tokio::runtime::Builder::new_multi_thread()
+                    .thread_name("galois-ffi")
+                    .enable_time()
+                    .build()?,
+            );
+        }
+        Ok(())
+    }
+
+    // starts `function` on a worker thread as soon as its arguments are resolved and, unless it
+    // is pure, the effectful calls before it are done. calls that do not depend on each other
+    // overlap; the caller only blocks when it needs a result.
+    pub fn spawn_call(
+        &self,
+        function: &str,
+        args: Vec<Promise>,
+        kwargs: Vec<(String, Promise)>,
+    ) -> Promise {
+        let Some(runtime) = &self.runtime else {
+            return failed(FFIError::CallError("Workers are not started".to_string()));
+        };
+        let Some((language, _)) = function.split_once('.') else {
+            return failed(FFIError::FunctionNotFound(function.to_string()));
+        };
+        let Some(protocol) = self.modules.get(language).map(Arc::clone) else {
+            return failed(FFIError::CallError(format!(
+                "Language not loaded: {}",
+                language
+            )));
+        };
+        let function = function.to_string();
+        let recorder = self.recorder.clone();
+        let cache = self.cache_for(&function);
+        let batcher = self.batchers.get(&function).cloned();
+        let limiters = self.limiters_for(&function);
+        // channels naming an argument are filled in with it when it is known already
+        let known = |promise: &Promise| match promise.clone().now_or_never()? {
+            Ok(Primitive::String(value)) => Some(value),
+            Ok(value) => Some(value.to_string()),
+            Err(_) => None,
+        };
+        let effect = self
+            .effect_of(&function)
+            .for_call(|name| match name.parse::<usize>() {
+                Ok(index) => args.get(index).and_then(known),
+                Err(_) => kwargs
+                    .iter()
+                    .find(|(kwarg, _)| kwarg == name)
+                    .and_then(|(_, promise)| known(promise)),
+            });
+        let delay = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.lock().ok())
+            .map(|mut schedule| schedule.next_delay());
+        let Ok(mut channels) = self.channels.lock() else {
+            return failed(FFIError::CallError("Effect channels poisoned".to_string()));
+        };
+
+        channels.order(&effect, |before| {
+            runtime
+                .spawn(async move {
+                    future::join_all(before).await;
+                    if let Some(delay) = delay {
+                        tokio::time::sleep(delay).await;
+                    }
+                    let args = future::try_join_all(args).await?;
+                    let kwargs = future::try_join_all(
+                        kwargs
+                            .into_iter()
+                            .map(|(name, value)| value.map_ok(move |value| (name, value))),
+                    )
+                    .await?;
+                    let recorded = recorder.as_ref().map(|_| (args.clone(), kwargs.clone()));
+                    let started_at = Instant::now();
+                    let key = cache
+                        .as_ref()
+                        .map(|cache| cache.key(&function, &args, &kwargs));
+
+                    let result = match cache.as_ref().zip(key.as_ref()) {
+                        Some((cache, key)) if let Some(value) = cache.get(key) => Ok(value),
+                        _ if let Some(batcher) = batcher => {
+                            let result = batcher.submit(args, kwargs, limiters).await;
+                            if let (Some(cache), Some(key), Ok(value)) = (&cache, &key, &result) {
+                                cache.put(key, &function, value);
+                            }
+                            result
+                        }
+                        _ => {
+                            let result = call_limited(&function, &limiters, || {
+                                invoke(
+                                    Arc::clone(&protocol),
+                                    function.clone(),
+                                    args.clone(),
+                                    kwargs.clone(),
+                                )
+                            })
+                            .await;
+                            if let (Some(cache), Some(key), Ok(value)) = (&cache, &key, &result) {
+                                cache.put(key, &function, value);
+                            }
+                            result
+                        }
+                    };
+
+                    if let (Some(recorder), Some((args, kwargs))) = (recorder, recorded) {
+                        recorder.record_call(
+                            &function,
+                            &args,
+                            &kwargs,
+                            &result,
+                            started_at.elapsed(),
+                        );
+                    }
+                    result
+                })
+                .map(|joined| joined.map_err(|e| FFIError::CallError(e.to_string()))?)
+                .boxed()
+                .shared()
+        })
+    }
+
+    pub fn qualify(&self, module_path: &str, function: &str) -> Result<String, FFIError> {
+        let qualified = format!("{}.{}", module_path, function);
+        match self.exports.get(module_path) {
+            Some(functions) if functions.iter().any(|f| f == function) => Ok(qualified),
+            _ => Err(FFIError::FunctionNotFound(qualified)),
+        }
+    }
+}
+
+// a call abandoned after its timeout must not keep the interpreter from exiting
+impl Drop for FFIBackend {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
         }
     }
 }
 
 impl FFIProtocol for FFIBackend {
     fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        let parts: Vec<&str> = module_path.split('.').collect();
-        let language = parts[0];
-        let module_name = parts[1..].join(".");
+        if let Some(functions) = self.exports.get(module_path) {
+            return Ok(functions.clone());
+        }
+
+        let (language, module_name) = module_path
+            .split_once('.')
+            .ok_or_else(|| FFIError::LoadError(format!("Missing language in: {}", module_path)))?;
 
         if !self.modules.contains_key(language) {
-            match language {
-                "python" => {
-                    self.modules
-                        .insert(language.to_string(), Box::new(PythonFFI::new()?));
+            let protocol: Box<dyn FFIProtocol> = match language {
+                _ if let Some(fixtures) = &self.mock => {
+                    Box::new(MockFFI::new(language, Arc::clone(fixtures)))
                 }
-                _ => return Err(format!("Unsupported language: {}", language).into()),
-            }
+                _ if let Some(recording) = &self.replay => {
+                    Box::new(ReplayFFI::new(language, Arc::clone(recording)))
+                }
+                "python" => Box::new(PythonFFI::new(self.search_path.clone())?),
+                "c" => Box::new(NativeFFI::new(self.search_path.clone())),
+                "proc" => Box::new(ProcFFI::new(self.search_path.clone())),
+                "sys" => Box::new(SysFFI::new()),
+                _ => return Err(FFIError::ProtocolNotImplemented(language.to_string()).into()),
+            };
+            self.modules
+                .insert(language.to_string(), Arc::new(RwLock::new(protocol)));
         }
 
-        let functions = self
-            .modules
-            .get_mut(language)
-            .unwrap()
-            .load_module(&module_name)?;
+        let mut protocol = self.modules[language]
+            .write()
+            .map_err(|_| FFIError::LoadError(format!("Backend poisoned: {}", language)))?;
+        let functions: Vec<String> = protocol
+            .load_module(module_name)?
+            .into_iter()
+            .filter(|func| !func.starts_with('_'))
+            .collect();
+        if let Some(cache) = &self.cache
+            && let Some(source) = protocol.source(module_name)
+        {
+            cache.note_source(module_path, &source);
+        }
+        for function in &functions {
+            let func_path = format!("{}.{}", module_name, function);
+            if let Some(effect) = protocol.effect(&func_path) {
+                self.own_effects
+                    .insert(format!("{}.{}", module_path, function), effect);
+            }
+            if let Some(ownership) = protocol.ownership(&func_path) {
+                self.own_ownership
+                    .insert(format!("{}.{}", module_path, function), ownership);
+            }
+            if let Some(batching) = protocol.batching(&func_path) {
+                let qualified = format!("{}.{}", module_path, function);
+                let batcher =
+                    Batcher::new(batching, &qualified, Arc::clone(&self.modules[language]));
+                self.batchers.insert(qualified, Arc::new(batcher));
+            }
+        }
+        drop(protocol);
 
-        functions.iter().for_each(|func| {
-            self.function_to_module
-                .insert(func.clone(), (language.to_string(), module_name.clone()));
-        });
+        self.exports
+            .insert(module_path.to_string(), functions.clone());
+        if let Some(recorder) = &self.recorder {
+            recorder.record_load(module_path, &functions);
+        }
 
         Ok(functions)
     }
 
-    fn call_function(&self, function: &str, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
-        let (language, module_name) = self
-            .function_to_module
-            .get(function)
+    // `func_path` is fully qualified: <language>.<module>.<function>
+    fn declare(
+        &mut self,
+        func_path: &str,
+        signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (language, path) = func_path
+            .split_once('.')
+            .ok_or_else(|| FFIError::FunctionNotFound(func_path.to_string()))?;
+        let defined = self
+            .modules
+            .get(language)
+            .ok_or_else(|| format!("Language not loaded: {}", language))?
+            .write()
+            .map_err(|_| FFIError::LoadError(format!("Backend poisoned: {}", language)))?
+            .declare(path, signature)?;
+
+        if defined && let Some(recorder) = &self.recorder {
+            recorder.record_declare(func_path);
+        }
+        if defined && let Some((module_path, function)) = func_path.rsplit_once('.') {
+            let functions = self.exports.entry(module_path.to_string()).or_default();
+            if !functions.iter().any(|f| f == function) {
+                functions.push(function.to_string());
+            }
+        }
+        Ok(defined)
+    }
+
+    // `function` is fully qualified: <language>.<module>.<function>
+    fn call_function(
+        &self,
+        function: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let (language, func_path) = function
+            .split_once('.')
             .ok_or_else(|| FFIError::FunctionNotFound(function.to_string()))?;
 
-        self.modules
+        let protocol = self
+            .modules
             .get(language)
-            .ok_or_else(|| format!("Language not loaded: {}", language))?
-            .call_function(&format!("{}.{}", module_name, function), args)
+            .ok_or_else(|| format!("Language not loaded: {}", language))?;
+        let read = || {
+            protocol
+                .read()
+                .map_err(|_| FFIError::CallError(format!("Backend poisoned: {}", language)))
+        };
+
+        let cache = self.cache_for(function);
+        let batched = self.batchers.contains_key(function);
+        let limiters = self.limiters_for(function);
+        if cache.is_none() && self.recorder.is_none() && !batched && limiters.is_empty() {
+            return read()?.call_function(func_path, args, kwargs);
+        }
+        let started_at = Instant::now();
+        let call = || {
+            // a batchable function called on its own gets a batch of one
+            let call_args = if batched {
+                batch::pack(vec![args.clone()])
+            } else {
+                args.clone()
+            };
+            let result = match &self.runtime {
+                Some(runtime) if !limiters.is_empty() => {
+                    runtime.block_on(call_limited(function, &limiters, || {
+                        invoke(
+                            Arc::clone(protocol),
+                            function.to_string(),
+                            call_args.clone(),
+                            kwargs.clone(),
+                        )
+                    }))
+                }
+                _ => read()?
+                    .call_function(func_path, call_args, kwargs.clone())
+                    .map_err(FFIError::from),
+            };
+            if batched {
+                let mut results = batch::unpack(function, result?, 1)?;
+                Ok(results.remove(0))
+            } else {
+                result
+            }
+        };
+        let result = match &cache {
+            Some(cache) => {
+                let key = cache.key(function, &args, &kwargs);
+                cache.get(&key).map(Ok).unwrap_or_else(|| {
+                    let result = call();
+                    if let Ok(value) = &result {
+                        cache.put(&key, function, value);
+                    }
+                    result
+                })
+            }
+            None => call(),
+        };
+        if let Some(recorder) = &self.recorder {
+            recorder.record_call(function, &args, &kwargs, &result, started_at.elapsed());
+        }
+        Ok(result?)
     }
 }
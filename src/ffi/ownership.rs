@@ -0,0 +1,32 @@
+// ffi/ownership.rs
+//
+// what a foreign function returns may be a resource only one part of a script can hold, such
+// as a file handle or a session. an affine value may be used at most once, a linear value
+// exactly once; `--affine` checks scripts for both before running them.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ownership {
+    Affine,
+    Linear,
+}
+
+impl Ownership {
+    pub fn parse(name: &str) -> Option<Ownership> {
+        match name {
+            "affine" => Some(Ownership::Affine),
+            "linear" => Some(Ownership::Linear),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ownership::Affine => write!(f, "affine"),
+            Ownership::Linear => write!(f, "linear"),
+        }
+    }
+}
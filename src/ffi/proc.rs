@@ -0,0 +1,186 @@
+// ffi/proc.rs
+//
+// out-of-process foreign functions: every `proc` module is an executable speaking
+// line-delimited JSON-RPC 2.0 on its stdin/stdout, see docs/protocol.md
+
+use super::json::{kwargs_to_json, primitive_from_json, primitive_to_json};
+use super::search_path::SearchPath;
+use super::{FFIError, FFIProtocol, ForeignException};
+use crate::syntax::Primitive;
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+
+pub const LIST_METHOD: &str = "rpc.list";
+
+struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Process {
+    fn request(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, FFIError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| FFIError::CallError("Process stdin is closed".to_string()))?;
+        writeln!(stdin, "{}", request)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| FFIError::CallError(format!("Cannot write to process: {}", e)))?;
+
+        loop {
+            let mut line = String::new();
+            let read = self
+                .stdout
+                .read_line(&mut line)
+                .map_err(|e| FFIError::CallError(format!("Cannot read from process: {}", e)))?;
+            if read == 0 {
+                return Err(FFIError::CallError(
+                    "Process exited before answering".to_string(),
+                ));
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response: serde_json::Value = serde_json::from_str(&line)
+                .map_err(|e| FFIError::CallError(format!("Malformed response: {}", e)))?;
+            if response["id"] != json!(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                return Err(FFIError::Exception(exception_from_rpc(error)));
+            }
+            return response.get("result").cloned().ok_or_else(|| {
+                FFIError::CallError("Response has neither result nor error".to_string())
+            });
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // closing stdin is the shutdown signal
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+fn exception_from_rpc(error: &serde_json::Value) -> ForeignException {
+    let data = &error["data"];
+    let string_list = |value: &serde_json::Value| -> Vec<String> {
+        value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    ForeignException {
+        kind: data["kind"].as_str().unwrap_or("RpcError").to_string(),
+        bases: string_list(&data["bases"]),
+        message: error["message"].as_str().unwrap_or_default().to_string(),
+        traceback: data["traceback"].as_str().map(String::from),
+    }
+}
+
+pub struct ProcFFI {
+    processes: HashMap<String, Mutex<Process>>,
+    search_path: SearchPath,
+}
+
+impl ProcFFI {
+    pub fn new(search_path: SearchPath) -> Self {
+        ProcFFI {
+            processes: HashMap::new(),
+            search_path,
+        }
+    }
+}
+
+impl FFIProtocol for ProcFFI {
+    fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let executable = self.search_path.resolve("proc", module_path, "")?;
+        let mut child = Command::new(&executable)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| FFIError::LoadError(format!("{}: {}", executable.display(), e)))?;
+
+        let mut process = Process {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            next_id: 0,
+        };
+
+        let functions = match process.request(LIST_METHOD, json!({}))? {
+            serde_json::Value::Array(names) => names
+                .iter()
+                .filter_map(|name| name.as_str().map(String::from))
+                .collect(),
+            other => {
+                return Err(FFIError::LoadError(format!(
+                    "{} answered {} with {}",
+                    executable.display(),
+                    LIST_METHOD,
+                    other
+                ))
+                .into());
+            }
+        };
+
+        self.processes
+            .insert(module_path.to_string(), Mutex::new(process));
+        Ok(functions)
+    }
+
+    fn source(&self, module_path: &str) -> Option<PathBuf> {
+        self.search_path.resolve("proc", module_path, "").ok()
+    }
+
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let (module_path, func_name) = func_path
+            .rsplit_once('.')
+            .ok_or_else(|| FFIError::FunctionNotFound(func_path.to_string()))?;
+        let process = self
+            .processes
+            .get(module_path)
+            .ok_or_else(|| FFIError::CallError(format!("Module not loaded: {}", module_path)))?;
+
+        let params = json!({
+            "args": args.iter().map(primitive_to_json).collect::<Vec<_>>(),
+            "kwargs": kwargs_to_json(&kwargs),
+        });
+        let result = process
+            .lock()
+            .map_err(|_| FFIError::CallError(format!("Process poisoned: {}", module_path)))?
+            .request(func_name, params)?;
+
+        Ok(primitive_from_json(&result)?)
+    }
+}
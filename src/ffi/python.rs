@@ -1,91 +1,326 @@
 // ffi/python.rs
 
-use super::FFIProtocol;
-use crate::syntax::{Primitive, Value};
+use super::batch::{Batching, DEFAULT_WINDOW};
+use super::effect::Effect;
+use super::ownership::Ownership;
+use super::search_path::SearchPath;
+use super::{CallResult, FFIError, FFIProtocol, ForeignException};
+use crate::syntax::{Primitive, Signature, Type};
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use num_bigint::BigInt;
 use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
-use pyo3::types::PyTuple;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyBool, PyCFunction, PyDict, PyList, PyTuple};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
 
+// `import galois_ffi` in a python module gets this, wherever the standard library is
+const GALOIS_HELPERS: &str = include_str!("../../std/ffi/python/galois_ffi.py");
+
+// holds no GIL token: every load and call acquires the GIL for its own duration, so calls
+// can come from any thread and python code blocked on I/O lets the others run
 pub struct PythonFFI {
-    py: Python<'static>,
     modules: HashMap<String, Py<PyModule>>,
+    search_path: SearchPath,
+    event_loop: GILOnceCell<PyObject>, // asyncio loop for coroutines, started on first use
+    returns: HashMap<String, Type>,    // declared by scripts, function -> its result
 }
 
 impl PythonFFI {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    pub fn new(search_path: SearchPath) -> Result<Self, Box<dyn Error>> {
         pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| -> PyResult<()> {
+            let helpers =
+                PyModule::from_code_bound(py, GALOIS_HELPERS, "galois_ffi.py", "galois_ffi")?;
+            py.import_bound("sys")?
+                .getattr("modules")?
+                .set_item("galois_ffi", helpers)
+        })
+        .map_err(exception_from_python)?;
         Ok(PythonFFI {
-            py: unsafe { Python::assume_gil_acquired() },
             modules: HashMap::new(),
+            search_path,
+            event_loop: GILOnceCell::new(),
+            returns: HashMap::new(),
         })
     }
 
-    fn convert_to_python(&self, value: &Value) -> PyObject {
-        pyo3::Python::<'static>::with_gil(|py| match value {
-            Value::Primitive(p) => match p {
-                Primitive::Int(i) => i.to_object(py),
-                Primitive::Float(f) => f.to_object(py),
-                Primitive::String(s) => s.to_object(py),
-                Primitive::Bool(b) => b.to_object(py),
-                Primitive::Array(_arr) => {
-                    unimplemented!()
+    // a single loop runs forever on a daemon thread; every coroutine a call returns is
+    // submitted to it, so any number of them can be in flight at once
+    fn event_loop<'py>(
+        &self,
+        py: Python<'py>,
+        asyncio: &Bound<'py, PyModule>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.event_loop
+            .get_or_try_init(py, || {
+                let event_loop = asyncio.call_method0("new_event_loop")?;
+                let thread_kwargs = PyDict::new_bound(py);
+                thread_kwargs.set_item("target", event_loop.getattr("run_forever")?)?;
+                thread_kwargs.set_item("name", "galois-asyncio")?;
+                thread_kwargs.set_item("daemon", true)?;
+                py.import_bound("threading")?
+                    .getattr("Thread")?
+                    .call((), Some(&thread_kwargs))?
+                    .call_method0("start")?;
+                Ok(event_loop.unbind())
+            })
+            .map(|event_loop| event_loop.bind(py).clone())
+    }
+
+    // the coroutine's result comes back through a done-callback, which runs on the loop thread
+    fn schedule(
+        &self,
+        py: Python<'_>,
+        asyncio: &Bound<'_, PyModule>,
+        coroutine: PyObject,
+        func_path: &str,
+    ) -> PyResult<BoxFuture<'static, Result<Primitive, FFIError>>> {
+        let event_loop = self.event_loop(py, asyncio)?;
+        let future = asyncio.call_method1("run_coroutine_threadsafe", (coroutine, event_loop))?;
+
+        let (sender, receiver) = oneshot::channel();
+        let sender = Mutex::new(Some(sender));
+        let func_path = func_path.to_string();
+        let declared = self.returns.get(&func_path).cloned();
+        let on_done = PyCFunction::new_closure_bound(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _kwargs: Option<&Bound<'_, PyDict>>| {
+                let result = match args.get_item(0)?.call_method0("result") {
+                    Ok(value) => convert_from_python(&func_path, declared.as_ref(), &value),
+                    Err(err) => Err(FFIError::from(exception_from_python(err))),
+                };
+                if let Some(sender) = sender.lock().ok().and_then(|mut sender| sender.take()) {
+                    let _ = sender.send(result);
                 }
+                PyResult::Ok(())
             },
-            Value::Function(name, params, body, _) => {
-                todo!()
-            }
-            _ => py.None(),
-        })
+        )?;
+        future.call_method1("add_done_callback", (on_done,))?;
+
+        Ok(receiver
+            .map(|result| {
+                result.unwrap_or_else(|_| {
+                    Err(FFIError::CallError(
+                        "Coroutine was dropped before finishing".to_string(),
+                    ))
+                })
+            })
+            .boxed())
     }
+}
 
-    fn convert_from_python(&self, obj: PyObject) -> Value {
-        pyo3::Python::<'static>::with_gil(|py| {
-            if let Ok(i) = obj.extract::<i64>(py) {
-                Value::Primitive(Primitive::Int(i))
-            } else if let Ok(f) = obj.extract::<f64>(py) {
-                Value::Primitive(Primitive::Float(f))
-            } else if let Ok(s) = obj.extract::<String>(py) {
-                Value::Primitive(Primitive::String(s))
-            } else if let Ok(b) = obj.extract::<bool>(py) {
-                Value::Primitive(Primitive::Bool(b))
-            } else if let Ok(_list) = obj.extract::<Vec<PyObject>>(py) {
-                Value::Primitive(Primitive::Array(unimplemented!()))
-            } else {
-                // TODO: Handle other types
-                Value::Primitive(Primitive::Bool(false))
+fn primitive_to_python(py: Python<'_>, primitive: &Primitive) -> PyObject {
+    match primitive {
+        Primitive::Int(i) => i.to_object(py),
+        Primitive::BigInt(i) => i.to_object(py),
+        Primitive::Float(f) => f.to_object(py),
+        Primitive::String(s) => s.to_object(py),
+        Primitive::Bool(b) => b.to_object(py),
+        Primitive::None => py.None(),
+        Primitive::Array(arr) => PyList::new_bound(
+            py,
+            arr.iter().map(|element| primitive_to_python(py, element)),
+        )
+        .into_any()
+        .unbind(),
+        Primitive::Record(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (name, value) in fields {
+                // both are always convertible
+                let _ = dict.set_item(name, primitive_to_python(py, value));
             }
-        })
+            dict.into_any().unbind()
+        }
+    }
+}
+
+// bools before ints, since python bools are ints too; ints too large for an `Int` are kept whole
+fn primitive_from_python(obj: &Bound<'_, PyAny>) -> Option<Primitive> {
+    if obj.is_none() {
+        Some(Primitive::None)
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Some(Primitive::Bool(b.is_true()))
+    } else if let Ok(i) = obj.extract::<i64>() {
+        Some(Primitive::Int(i))
+    } else if let Ok(i) = obj.extract::<BigInt>() {
+        Some(Primitive::integer(i))
+    } else if let Ok(f) = obj.extract::<f64>() {
+        Some(Primitive::Float(f))
+    } else if let Ok(s) = obj.extract::<String>() {
+        Some(Primitive::String(s))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        // only dicts keyed by strings are records
+        dict.iter()
+            .map(|(name, value)| {
+                Some((
+                    name.extract::<String>().ok()?,
+                    primitive_from_python(&value)?,
+                ))
+            })
+            .collect::<Option<_>>()
+            .map(Primitive::Record)
+    } else if let Ok(list) = obj.extract::<Vec<Bound<'_, PyAny>>>() {
+        list.iter()
+            .map(primitive_from_python)
+            .collect::<Option<Vec<_>>>()
+            .map(Primitive::Array)
+    } else {
+        None
     }
 }
 
 impl FFIProtocol for PythonFFI {
     // returns a list of function names
     fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        Python::with_gil(|py| {
-            let module_file = format!("std/ffi/python/{}.py", module_path.replace('.', "/"));
-            let module_code = fs::read_to_string(&module_file)
-                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        let module_file = self.search_path.resolve("python", module_path, "py")?;
+        let module_code = fs::read_to_string(&module_file)?;
 
-            let module = PyModule::from_code_bound(py, &module_code, &module_file, module_path)?;
+        Python::with_gil(|py| -> PyResult<Vec<String>> {
+            let module = PyModule::from_code_bound(
+                py,
+                &module_code,
+                &module_file.to_string_lossy(),
+                module_path,
+            )?;
 
             self.modules
                 .insert(module_path.to_string(), module.clone().into());
 
-            let function_names = module
-                .dir()?
-                .iter()
-                .map(|item| item.to_string())
-                .collect::<Vec<String>>();
+            // `__all__` wins when present, otherwise every public callable the module defines
+            // itself: what it imports, like `Path` from pathlib, belongs to another module
+            let all = module.getattr("__all__").ok();
+            let names: Vec<String> = match &all {
+                Some(all) => all.extract()?,
+                None => module
+                    .dir()?
+                    .iter()
+                    .map(|item| item.to_string())
+                    .filter(|name| !name.starts_with('_'))
+                    .collect(),
+            };
+            let module_name = module.name()?.to_string();
+            let mut function_names = Vec::new();
+            for name in names {
+                let value = module.getattr(name.as_str())?;
+                // a callable that does not say where it is from is taken to be the module's own
+                let defined_in: Option<String> = value
+                    .getattr("__module__")
+                    .ok()
+                    .and_then(|defined_in| defined_in.extract().ok());
+                let defined_here =
+                    all.is_some() || defined_in.is_none_or(|defined_in| defined_in == module_name);
+                if value.is_callable() && defined_here {
+                    function_names.push(name);
+                }
+            }
 
             Ok(function_names)
         })
+        .map_err(exception_from_python)
     }
 
-    fn call_function(&self, func_path: &str, args: Vec<Value>) -> Result<Value, Box<dyn Error>> {
+    // results are checked against the declared type in the interpreter, but an object galois
+    // has no value for is reported here, along with the contract it breaks
+    fn declare(
+        &mut self,
+        func_path: &str,
+        signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        match signature.and_then(|signature| signature.ret.clone()) {
+            Some(ret) => self.returns.insert(func_path.to_string(), ret),
+            None => self.returns.remove(func_path),
+        };
+        Ok(false)
+    }
+
+    fn source(&self, module_path: &str) -> Option<PathBuf> {
+        self.search_path.resolve("python", module_path, "py").ok()
+    }
+
+    // set by the `galois_ffi.pure`, `io` and `effect` decorators: the effect's channels,
+    // none for pure, or `None` for io
+    fn effect(&self, func_path: &str) -> Option<Effect> {
+        let (module_path, func_name) = func_path.rsplit_once('.')?;
+        let module = self.modules.get(module_path)?;
         Python::with_gil(|py| {
+            let marker = module
+                .getattr(py, func_name)
+                .and_then(|func| func.getattr(py, "__galois_effect__"))
+                .ok()?;
+            match marker.extract::<Option<Vec<String>>>(py).ok()? {
+                Some(channels) => Some(Effect::named(channels)),
+                None => Some(Effect::Io),
+            }
+        })
+    }
+
+    // set by the `galois_ffi.affine` and `linear` decorators
+    fn ownership(&self, func_path: &str) -> Option<Ownership> {
+        let (module_path, func_name) = func_path.rsplit_once('.')?;
+        let module = self.modules.get(module_path)?;
+        Python::with_gil(|py| {
+            let marker = module
+                .getattr(py, func_name)
+                .and_then(|func| func.getattr(py, "__galois_ownership__"))
+                .ok()?;
+            Ownership::parse(&marker.extract::<String>(py).ok()?)
+        })
+    }
+
+    // set by the `galois_ffi.batch` decorator
+    fn batching(&self, func_path: &str) -> Option<Batching> {
+        let (module_path, func_name) = func_path.rsplit_once('.')?;
+        let module = self.modules.get(module_path)?;
+        Python::with_gil(|py| {
+            let marker = module
+                .getattr(py, func_name)
+                .and_then(|func| func.getattr(py, "__galois_batch__"))
+                .ok()?;
+            let marker = marker.bind(py);
+            let max_size = marker
+                .get_item("max_size")
+                .and_then(|max_size| max_size.extract::<Option<usize>>())
+                .ok()
+                .flatten();
+            let window = marker
+                .get_item("window_ms")
+                .and_then(|window| window.extract::<f64>())
+                .map(|window| Duration::from_secs_f64(window.max(0.0) / 1000.0))
+                .unwrap_or(DEFAULT_WINDOW);
+            Some(Batching { max_size, window })
+        })
+    }
+
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        match self.begin_call(func_path, args, kwargs)? {
+            CallResult::Ready(value) => Ok(value),
+            // the GIL is not held here, so the event loop thread can run the coroutine
+            CallResult::Awaiting(rest) => Ok(futures::executor::block_on(rest)?),
+        }
+    }
+
+    fn begin_call(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<CallResult, Box<dyn Error>> {
+        let converted = Python::with_gil(|py| -> PyResult<Result<CallResult, FFIError>> {
             let parts: Vec<&str> = func_path.split('.').collect();
             let (module_parts, func_name) = parts.split_at(parts.len() - 1);
             let module_path = module_parts.join(".");
@@ -96,16 +331,103 @@ impl FFIProtocol for PythonFFI {
             })?;
             let func = module.getattr(py, func_name)?;
 
-            let py_args: Vec<PyObject> =
-                args.iter().map(|arg| self.convert_to_python(arg)).collect();
+            let py_args: Vec<PyObject> = args
+                .iter()
+                .map(|arg| primitive_to_python(py, arg))
+                .collect();
 
-            let result = if py_args.is_empty() {
+            let result = if py_args.is_empty() && kwargs.is_empty() {
                 func.call0(py)?
-            } else {
+            } else if kwargs.is_empty() {
                 func.call1(py, PyTuple::new_bound(py, py_args.as_slice()))?
+            } else {
+                let py_kwargs = PyDict::new_bound(py);
+                for (name, value) in &kwargs {
+                    py_kwargs.set_item(name, primitive_to_python(py, value))?;
+                }
+                func.call_bound(
+                    py,
+                    PyTuple::new_bound(py, py_args.as_slice()),
+                    Some(&py_kwargs),
+                )?
             };
-            Ok(self.convert_from_python(result))
+
+            let asyncio = py.import_bound("asyncio")?;
+            if asyncio
+                .call_method1("iscoroutine", (&result,))?
+                .is_truthy()?
+            {
+                return self
+                    .schedule(py, &asyncio, result, func_path)
+                    .map(|rest| Ok(CallResult::Awaiting(rest)));
+            }
+            let declared = self.returns.get(func_path);
+            Ok(convert_from_python(func_path, declared, result.bind(py)).map(CallResult::Ready))
         })
-        .map_err(|e: PyErr| Box::new(e) as Box<dyn Error>)
+        .map_err(exception_from_python)?;
+        Ok(converted?)
     }
 }
+
+// objects galois has no value for are named, along with the contract they break when the script
+// declared what the function returns
+fn convert_from_python(
+    func_path: &str,
+    declared: Option<&Type>,
+    obj: &Bound<'_, PyAny>,
+) -> Result<Primitive, FFIError> {
+    if let Some(value) = primitive_from_python(obj) {
+        return Ok(value);
+    }
+    let repr = obj
+        .repr()
+        .map(|repr| repr.to_string())
+        .unwrap_or_else(|_| "an object".to_string());
+    Err(FFIError::TypeMismatch(match declared {
+        Some(ty) if *ty != Type::Any => format!(
+            "Function 'python.{}' is declared to return {}, but returned {}",
+            func_path, ty, repr
+        ),
+        _ => format!(
+            "Function 'python.{}' returned {}, which galois has no value for",
+            func_path, repr
+        ),
+    }))
+}
+
+fn exception_from_python(err: PyErr) -> Box<dyn Error> {
+    Python::with_gil(|py| {
+        let ty = err.get_type_bound(py);
+        let kind = ty
+            .name()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|_| "Exception".to_string());
+        let bases = ty
+            .getattr("__mro__")
+            .and_then(|mro| {
+                mro.iter()?
+                    .skip(1)
+                    .map(|base| base?.getattr("__name__")?.extract::<String>())
+                    .collect::<PyResult<Vec<String>>>()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|base| base != "object")
+            .collect();
+        let message = err
+            .value_bound(py)
+            .str()
+            .map(|message| message.to_string())
+            .unwrap_or_default();
+        let traceback = err
+            .traceback_bound(py)
+            .and_then(|traceback| traceback.format().ok());
+
+        Box::new(FFIError::Exception(ForeignException {
+            kind,
+            bases,
+            message,
+            traceback,
+        })) as Box<dyn Error>
+    })
+}
@@ -0,0 +1,291 @@
+// ffi/record.rs
+//
+// `--record <log>` writes every module load, declaration and foreign call of a run to a
+// JSON-lines log; `--replay <log>` answers from such a log instead of loading any backend.
+// one object per line:
+//
+//     {"load": "python.llm", "functions": ["complete"]}
+//     {"declare": "c.libm.cos"}
+//     {"call": "python.llm.complete", "backend": "python", "args": ["hi"], "kwargs": {},
+//      "result": "hello", "ms": 812.4}
+//
+// a failed call has `"error": {"kind", "bases", "message", "traceback"}` instead of a result, or
+// `{"variant", "message"}` for errors that are not foreign exceptions, e.g. a `TypeMismatch`.
+// floats json has no number for, such as nan, are stored as `{"$float": "NaN"}`.
+
+use super::json::{kwargs_to_stored_json, primitive_from_stored_json, primitive_to_stored_json};
+use super::{FFIError, FFIProtocol, ForeignException};
+use crate::syntax::{Primitive, Signature};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub struct Recorder {
+    log: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder, FFIError> {
+        let file = File::create(path)
+            .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+        Ok(Recorder {
+            log: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn record_load(&self, module_path: &str, functions: &[String]) {
+        self.write(json!({ "load": module_path, "functions": functions }));
+    }
+
+    pub fn record_declare(&self, func_path: &str) {
+        self.write(json!({ "declare": func_path }));
+    }
+
+    pub fn record_call(
+        &self,
+        function: &str,
+        args: &[Primitive],
+        kwargs: &[(String, Primitive)],
+        result: &Result<Primitive, FFIError>,
+        latency: Duration,
+    ) {
+        let mut entry = json!({
+            "call": function,
+            "backend": function.split('.').next().unwrap_or_default(),
+            "args": args.iter().map(primitive_to_stored_json).collect::<Vec<_>>(),
+            "kwargs": kwargs_to_stored_json(kwargs),
+            "ms": latency.as_secs_f64() * 1000.0,
+        });
+        match result {
+            Ok(value) => entry["result"] = primitive_to_stored_json(value),
+            Err(error) => entry["error"] = error_to_json(error),
+        }
+        self.write(entry);
+    }
+
+    // flushed line by line, so the log of a run that crashes is still complete
+    fn write(&self, entry: serde_json::Value) {
+        if let Ok(mut log) = self.log.lock() {
+            let _ = writeln!(log, "{}", entry).and_then(|_| log.flush());
+        }
+    }
+}
+
+struct RecordedCall {
+    function: String,
+    args: serde_json::Value,
+    kwargs: serde_json::Value,
+    response: Result<Primitive, FFIError>,
+    replayed: AtomicBool,
+}
+
+pub struct Recording {
+    loads: HashMap<String, Vec<String>>,
+    declared: HashSet<String>,
+    calls: Vec<RecordedCall>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Recording, FFIError> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| FFIError::LoadError(format!("{}: {}", path.display(), e)))?;
+
+        let mut recording = Recording {
+            loads: HashMap::new(),
+            declared: HashSet::new(),
+            calls: Vec::new(),
+        };
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| {
+                FFIError::LoadError(format!("{}:{}: {}", path.display(), number + 1, reason))
+            };
+            let entry: serde_json::Value =
+                serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+
+            if let Some(module_path) = entry["load"].as_str() {
+                let functions = entry["functions"]
+                    .as_array()
+                    .map(|functions| {
+                        functions
+                            .iter()
+                            .filter_map(|f| f.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                recording.loads.insert(module_path.to_string(), functions);
+            } else if let Some(func_path) = entry["declare"].as_str() {
+                recording.declared.insert(func_path.to_string());
+            } else if let Some(function) = entry["call"].as_str() {
+                let response = match entry.get("error") {
+                    Some(error) => Err(error_from_json(error)),
+                    None => primitive_from_stored_json(&entry["result"])
+                        .map_err(|e| invalid(e.to_string()))
+                        .map(Ok)?,
+                };
+                recording.calls.push(RecordedCall {
+                    function: function.to_string(),
+                    args: entry["args"].clone(),
+                    kwargs: entry["kwargs"].clone(),
+                    response,
+                    replayed: AtomicBool::new(false),
+                });
+            } else {
+                return Err(invalid(
+                    "expected a load, declare or call entry".to_string(),
+                ));
+            }
+        }
+        Ok(recording)
+    }
+}
+
+// errors keep their variant, so a replayed run catches and reports them as the recorded one did
+fn error_to_json(error: &FFIError) -> serde_json::Value {
+    let (variant, message) = match error {
+        FFIError::Exception(exception) => {
+            return json!({
+                "kind": exception.kind,
+                "bases": exception.bases,
+                "message": exception.message,
+                "traceback": exception.traceback,
+            });
+        }
+        FFIError::ModuleNotFound(name, searched) => {
+            return json!({
+                "variant": "ModuleNotFound",
+                "message": name,
+                "searched": searched,
+            });
+        }
+        FFIError::AmbiguousFunction(name, providers) => {
+            return json!({
+                "variant": "AmbiguousFunction",
+                "message": name,
+                "providers": providers,
+            });
+        }
+        FFIError::ProtocolNotImplemented(message) => ("ProtocolNotImplemented", message),
+        FFIError::FunctionNotFound(message) => ("FunctionNotFound", message),
+        FFIError::LoadError(message) => ("LoadError", message),
+        FFIError::CallError(message) => ("CallError", message),
+        FFIError::TypeMismatch(message) => ("TypeMismatch", message),
+    };
+    json!({ "variant": variant, "message": message })
+}
+
+fn error_from_json(error: &serde_json::Value) -> FFIError {
+    let message = error["message"].as_str().unwrap_or_default().to_string();
+    let strings = |name: &str| -> Vec<String> {
+        error[name]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    if let Some(kind) = error["kind"].as_str() {
+        return FFIError::Exception(ForeignException {
+            kind: kind.to_string(),
+            bases: strings("bases"),
+            message,
+            traceback: error["traceback"].as_str().map(String::from),
+        });
+    }
+    match error["variant"].as_str() {
+        Some("ModuleNotFound") => FFIError::ModuleNotFound(
+            message,
+            strings("searched").into_iter().map(PathBuf::from).collect(),
+        ),
+        Some("AmbiguousFunction") => FFIError::AmbiguousFunction(message, strings("providers")),
+        Some("ProtocolNotImplemented") => FFIError::ProtocolNotImplemented(message),
+        Some("FunctionNotFound") => FFIError::FunctionNotFound(message),
+        Some("LoadError") => FFIError::LoadError(message),
+        Some("TypeMismatch") => FFIError::TypeMismatch(message),
+        _ => FFIError::CallError(message),
+    }
+}
+
+pub struct ReplayFFI {
+    language: String,
+    recording: Arc<Recording>,
+}
+
+impl ReplayFFI {
+    pub fn new(language: &str, recording: Arc<Recording>) -> Self {
+        ReplayFFI {
+            language: language.to_string(),
+            recording,
+        }
+    }
+}
+
+impl FFIProtocol for ReplayFFI {
+    fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let qualified = format!("{}.{}", self.language, module_path);
+        match self.recording.loads.get(&qualified) {
+            Some(functions) => Ok(functions.clone()),
+            None => Err(FFIError::ModuleNotFound(
+                format!("{} (not in the recording)", qualified),
+                Vec::new(),
+            )
+            .into()),
+        }
+    }
+
+    fn declare(
+        &mut self,
+        func_path: &str,
+        _signature: Option<&Signature>,
+    ) -> Result<bool, Box<dyn Error>> {
+        Ok(self
+            .recording
+            .declared
+            .contains(&format!("{}.{}", self.language, func_path)))
+    }
+
+    // identical calls are answered in the order they were recorded
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let function = format!("{}.{}", self.language, func_path);
+        let args = serde_json::Value::Array(args.iter().map(primitive_to_stored_json).collect());
+        let kwargs = kwargs_to_stored_json(&kwargs);
+
+        let call = self
+            .recording
+            .calls
+            .iter()
+            .filter(|call| call.function == function && call.args == args && call.kwargs == kwargs)
+            .find(|call| {
+                call.replayed
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            })
+            .ok_or_else(|| {
+                FFIError::CallError(format!(
+                    "No recorded call left for {} with args {} and kwargs {}",
+                    function, args, kwargs
+                ))
+            })?;
+
+        match &call.response {
+            Ok(value) => Ok(value.clone()),
+            Err(error) => Err(error.clone().into()),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// ffi/schedule.rs
+//
+// `--schedule-seed`: every call started under `--parallel` waits a moment drawn from the seed
+// before it runs. under different seeds a script runs its calls in different orders wherever
+// the order is free, so what its output depends on shows up in tests rather than in production.
+
+use std::time::Duration;
+
+const MAX_DELAY_MS: u64 = 20;
+
+pub struct Schedule {
+    state: u64,
+}
+
+impl Schedule {
+    pub fn new(seed: u64) -> Self {
+        Schedule { state: seed }
+    }
+
+    // splitmix64: the same seed gives the same delays, call by call
+    pub fn next_delay(&mut self) -> Duration {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Duration::from_micros(z % (MAX_DELAY_MS * 1000))
+    }
+}
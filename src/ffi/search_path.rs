@@ -0,0 +1,111 @@
+// ffi/search_path.rs
+
+use super::FFIError;
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub const GALOIS_PATH: &str = "GALOIS_PATH";
+
+// every root mirrors the layout of `std/`: galois modules live in `<root>/`,
+// foreign modules in `<root>/ffi/<language>/`
+#[derive(Clone, Debug, Default)]
+pub struct SearchPath {
+    roots: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        SearchPath { roots }
+    }
+
+    /// Builds the default search path, in lookup order: explicit `--path` entries,
+    /// `GALOIS_PATH`, the directory of the script being run, then the standard library
+    /// found next to the executable.
+    pub fn from_env(cli_paths: Vec<PathBuf>, script: Option<&Path>) -> Self {
+        let mut roots = cli_paths;
+
+        if let Some(paths) = env::var_os(GALOIS_PATH) {
+            roots.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+        }
+
+        if let Some(dir) = script.and_then(Path::parent) {
+            roots.push(if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir.to_path_buf()
+            });
+        }
+
+        if let Some(std_dir) = find_std_dir() {
+            roots.push(std_dir);
+        }
+
+        let mut search_path = SearchPath::new(Vec::new());
+        for root in roots {
+            search_path.push(root);
+        }
+        search_path
+    }
+
+    pub fn push(&mut self, root: PathBuf) {
+        if !self.roots.contains(&root) {
+            self.roots.push(root);
+        }
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn candidates(&self, language: &str, module_path: &str, extension: &str) -> Vec<PathBuf> {
+        let mut relative = module_path.replace('.', "/");
+        if !extension.is_empty() {
+            relative = format!("{}.{}", relative, extension);
+        }
+        self.roots
+            .iter()
+            .map(|root| root.join("ffi").join(language).join(&relative))
+            .collect()
+    }
+
+    // `church` -> `<root>/church.gal`
+    pub fn resolve_source(&self, module_path: &str) -> Result<PathBuf, FFIError> {
+        let relative = format!("{}.gal", module_path.replace('.', "/"));
+        let candidates: Vec<PathBuf> = self.roots.iter().map(|root| root.join(&relative)).collect();
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+            .ok_or_else(|| FFIError::ModuleNotFound(format!("galois.{}", module_path), candidates))
+    }
+
+    pub fn resolve(
+        &self,
+        language: &str,
+        module_path: &str,
+        extension: &str,
+    ) -> Result<PathBuf, FFIError> {
+        let candidates = self.candidates(language, module_path, extension);
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+            .ok_or_else(|| {
+                FFIError::ModuleNotFound(format!("{}.{}", language, module_path), candidates)
+            })
+    }
+}
+
+// walks up from the executable so both `target/<profile>/galois` and installed layouts find `std/`
+fn find_std_dir() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    exe.ancestors()
+        .skip(1)
+        .flat_map(|dir| {
+            [
+                dir.join("std"),
+                dir.join("share").join("galois").join("std"),
+            ]
+        })
+        .find(|dir| dir.join("ffi").is_dir())
+}
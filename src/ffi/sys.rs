@@ -0,0 +1,332 @@
+// ffi/sys.rs
+//
+// modules built into the interpreter, under the `sys` language.
+// `sys.process` runs programs and pipelines of programs.
+
+use super::effect::Effect;
+use super::{FFIError, FFIProtocol, ForeignException};
+use crate::syntax::Primitive;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+const PROCESS_FUNCTIONS: [&str; 5] = ["run", "pipe", "stdout", "stderr", "exit_code"];
+
+pub struct SysFFI;
+
+impl SysFFI {
+    pub fn new() -> Self {
+        SysFFI
+    }
+}
+
+// what every command of a pipeline shares
+struct Options {
+    stdin: Option<String>,
+    env: Vec<(String, String)>,
+    cwd: Option<String>,
+}
+
+impl FFIProtocol for SysFFI {
+    fn load_module(&mut self, module_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        match module_path {
+            "process" => Ok(PROCESS_FUNCTIONS.iter().map(|f| f.to_string()).collect()),
+            _ => Err(FFIError::ModuleNotFound(format!("sys.{}", module_path), Vec::new()).into()),
+        }
+    }
+
+    // running a program may do anything, reading a result it returned does nothing
+    fn effect(&self, func_path: &str) -> Option<Effect> {
+        match func_path {
+            "process.run" | "process.pipe" => Some(Effect::Io),
+            "process.stdout" | "process.stderr" | "process.exit_code" => Some(Effect::Pure),
+            _ => None,
+        }
+    }
+
+    fn call_function(
+        &self,
+        func_path: &str,
+        args: Vec<Primitive>,
+        kwargs: Vec<(String, Primitive)>,
+    ) -> Result<Primitive, Box<dyn Error>> {
+        let result = match func_path {
+            "process.run" => {
+                let [command, command_args, stdin, env, cwd] = bind_arguments(
+                    func_path,
+                    ["command", "args", "stdin", "env", "cwd"],
+                    1,
+                    args,
+                    kwargs,
+                )?;
+                let program = expect_string(func_path, "command", command)?;
+                let command_args = command_args
+                    .map(|value| expect_strings(func_path, "args", Some(value)))
+                    .transpose()?
+                    .unwrap_or_default();
+                let options = options(func_path, stdin, env, cwd)?;
+                run_pipeline(&[(program, command_args)], &options)?
+            }
+            "process.pipe" => {
+                let [commands, stdin, env, cwd] = bind_arguments(
+                    func_path,
+                    ["commands", "stdin", "env", "cwd"],
+                    1,
+                    args,
+                    kwargs,
+                )?;
+                let commands = match commands {
+                    Some(Primitive::Array(commands)) if !commands.is_empty() => commands
+                        .into_iter()
+                        .map(|command| {
+                            let mut words =
+                                expect_strings(func_path, "commands", Some(command))?.into_iter();
+                            let program = words.next().ok_or_else(|| {
+                                FFIError::CallError(format!(
+                                    "{}: every command needs a program",
+                                    func_path
+                                ))
+                            })?;
+                            Ok((program, words.collect()))
+                        })
+                        .collect::<Result<Vec<_>, FFIError>>()?,
+                    _ => {
+                        return Err(FFIError::CallError(format!(
+                            "{}: 'commands' must be a non-empty array of [program, args...] arrays",
+                            func_path
+                        ))
+                        .into());
+                    }
+                };
+                let options = options(func_path, stdin, env, cwd)?;
+                run_pipeline(&commands, &options)?
+            }
+            "process.stdout" | "process.stderr" | "process.exit_code" => {
+                let [result] = bind_arguments(func_path, ["result"], 1, args, kwargs)?;
+                let index = match func_path {
+                    "process.stdout" => 0,
+                    "process.stderr" => 1,
+                    _ => 2,
+                };
+                match result {
+                    Some(Primitive::Array(mut fields)) if fields.len() == 3 => {
+                        fields.swap_remove(index)
+                    }
+                    _ => {
+                        return Err(FFIError::CallError(format!(
+                            "{}: expected the result of run or pipe",
+                            func_path
+                        ))
+                        .into());
+                    }
+                }
+            }
+            _ => return Err(FFIError::FunctionNotFound(format!("sys.{}", func_path)).into()),
+        };
+        Ok(result)
+    }
+}
+
+// positional arguments fill parameters in order, named ones by name; the first `required`
+// parameters must be given
+fn bind_arguments<const N: usize>(
+    function: &str,
+    params: [&str; N],
+    required: usize,
+    args: Vec<Primitive>,
+    kwargs: Vec<(String, Primitive)>,
+) -> Result<[Option<Primitive>; N], FFIError> {
+    if args.len() > N {
+        return Err(FFIError::CallError(format!(
+            "{} takes at most {} arguments, got {}",
+            function,
+            N,
+            args.len()
+        )));
+    }
+    let mut bound: [Option<Primitive>; N] = std::array::from_fn(|_| None);
+    for (slot, arg) in bound.iter_mut().zip(args) {
+        *slot = Some(arg);
+    }
+    for (name, value) in kwargs {
+        let index = params.iter().position(|p| *p == name).ok_or_else(|| {
+            FFIError::CallError(format!("{} has no parameter named '{}'", function, name))
+        })?;
+        if bound[index].replace(value).is_some() {
+            return Err(FFIError::CallError(format!(
+                "{} got multiple values for '{}'",
+                function, name
+            )));
+        }
+    }
+    if let Some(missing) = params[..required]
+        .iter()
+        .zip(&bound)
+        .find_map(|(param, value)| value.is_none().then_some(param))
+    {
+        return Err(FFIError::CallError(format!(
+            "{} is missing an argument for '{}'",
+            function, missing
+        )));
+    }
+    Ok(bound)
+}
+
+fn expect_string(
+    function: &str,
+    param: &str,
+    value: Option<Primitive>,
+) -> Result<String, FFIError> {
+    match value {
+        Some(Primitive::String(s)) => Ok(s),
+        other => Err(FFIError::CallError(format!(
+            "{}: '{}' must be a string, got {}",
+            function,
+            param,
+            other.map_or("nothing".to_string(), |v| v.to_string())
+        ))),
+    }
+}
+
+fn expect_strings(
+    function: &str,
+    param: &str,
+    value: Option<Primitive>,
+) -> Result<Vec<String>, FFIError> {
+    match value {
+        Some(Primitive::Array(elements)) => elements
+            .into_iter()
+            .map(|element| expect_string(function, param, Some(element)))
+            .collect(),
+        other => Err(FFIError::CallError(format!(
+            "{}: '{}' must be an array of strings, got {}",
+            function,
+            param,
+            other.map_or("nothing".to_string(), |v| v.to_string())
+        ))),
+    }
+}
+
+// `env` entries are "NAME=value" strings, added to the inherited environment
+fn options(
+    function: &str,
+    stdin: Option<Primitive>,
+    env: Option<Primitive>,
+    cwd: Option<Primitive>,
+) -> Result<Options, FFIError> {
+    let env = match env {
+        Some(env) => expect_strings(function, "env", Some(env))?
+            .into_iter()
+            .map(|entry| match entry.split_once('=') {
+                Some((name, value)) => Ok((name.to_string(), value.to_string())),
+                None => Err(FFIError::CallError(format!(
+                    "{}: env entries look like \"NAME=value\", got \"{}\"",
+                    function, entry
+                ))),
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(Options {
+        stdin: stdin
+            .map(|stdin| expect_string(function, "stdin", Some(stdin)))
+            .transpose()?,
+        env,
+        cwd: cwd
+            .map(|cwd| expect_string(function, "cwd", Some(cwd)))
+            .transpose()?,
+    })
+}
+
+// runs the commands with each stdout connected to the next stdin. the result is
+// [stdout of the last command, stderr of all commands, exit code]; like `set -o pipefail`,
+// the exit code is the last non-zero one.
+fn run_pipeline(
+    commands: &[(String, Vec<String>)],
+    options: &Options,
+) -> Result<Primitive, FFIError> {
+    let mut children: Vec<Child> = Vec::new();
+    let mut stderr_readers: Vec<JoinHandle<Vec<u8>>> = Vec::new();
+    let mut stdin_writer = None;
+    let mut previous_stdout: Option<ChildStdout> = None;
+
+    for (program, args) in commands {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(options.env.iter().map(|(name, value)| (name, value)))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(match previous_stdout.take() {
+                Some(stdout) => Stdio::from(stdout),
+                None if options.stdin.is_some() => Stdio::piped(),
+                None => Stdio::null(),
+            });
+        if let Some(cwd) = &options.cwd {
+            command.current_dir(cwd);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                for mut started in children {
+                    let _ = started.kill();
+                    let _ = started.wait();
+                }
+                return Err(FFIError::Exception(ForeignException::new(
+                    "ProcessError",
+                    &format!("cannot run {}: {}", program, e),
+                )));
+            }
+        };
+
+        // written from a thread so a command producing output before reading all of its
+        // input cannot deadlock us
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), options.stdin.clone()) {
+            stdin_writer = Some(thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            }));
+        }
+        if let Some(mut stderr) = child.stderr.take() {
+            stderr_readers.push(thread::spawn(move || {
+                let mut buffer = Vec::new();
+                let _ = stderr.read_to_end(&mut buffer);
+                buffer
+            }));
+        }
+        previous_stdout = child.stdout.take();
+        children.push(child);
+    }
+
+    let mut stdout = Vec::new();
+    if let Some(mut last) = previous_stdout {
+        last.read_to_end(&mut stdout)
+            .map_err(|e| FFIError::CallError(format!("Cannot read process output: {}", e)))?;
+    }
+
+    let mut exit_code = 0;
+    for mut child in children {
+        let status = child
+            .wait()
+            .map_err(|e| FFIError::CallError(format!("Cannot wait for process: {}", e)))?;
+        // killed by a signal: no exit code
+        let code = status.code().unwrap_or(-1);
+        if code != 0 {
+            exit_code = code;
+        }
+    }
+    if let Some(writer) = stdin_writer {
+        let _ = writer.join();
+    }
+    let stderr: Vec<u8> = stderr_readers
+        .into_iter()
+        .flat_map(|reader| reader.join().unwrap_or_default())
+        .collect();
+
+    Ok(Primitive::Array(vec![
+        Primitive::String(String::from_utf8_lossy(&stdout).into_owned()),
+        Primitive::String(String::from_utf8_lossy(&stderr).into_owned()),
+        Primitive::Int(exit_code as i64),
+    ]))
+}
@@ -0,0 +1,399 @@
+// interpreter/affine.rs
+//
+// `--affine`: before a script runs, checks that each affine value it binds is used at most
+// once and each linear value exactly once. such values are returned by foreign functions
+// declared `#[affine]` or `#[linear]`, and by galois functions returning one of those.
+//
+// a use is any mention of the name once it is bound: passing it to a function, returning it,
+// putting it in an array, binding it to another name, or mentioning it in a nested function.
+// a function that captures an owned value holds it, and is owned itself: calling it is a use.
+// parameters are not tracked, what a caller passes is checked where the caller bound it.
+
+use super::evaluator::{Interpreter, InterpreterError};
+use crate::ffi::ownership::Ownership;
+use crate::syntax::{Expr, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+
+// an owned value and where it comes from, e.g. "returned by python.files.open"
+type Origin = (Ownership, String);
+
+#[derive(Clone)]
+struct Owned {
+    ownership: Ownership,
+    origin: String,
+    used: Option<String>, // the expression that used it first
+    reported: bool,
+}
+
+// what is bound in one function body, or at the top of the script
+#[derive(Clone, Default)]
+struct Scope {
+    owned: BTreeMap<String, Owned>,
+    returns: HashMap<String, Origin>, // nested functions returning owned values
+    locals: HashSet<String>,          // parameters and other bindings shadowing global names
+}
+
+impl Scope {
+    fn binds(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Variable(name) => {
+                self.owned.contains_key(name)
+                    || self.returns.contains_key(name)
+                    || self.locals.contains(name)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    violations: Vec<String>,
+    returns: HashMap<String, Option<Origin>>, // galois function -> what it returns
+}
+
+impl Interpreter {
+    // the script's declarations must have been evaluated, so that names resolve
+    pub(super) fn check_affine(&mut self, exprs: &[Rc<Expr>]) -> Result<(), InterpreterError> {
+        let mut report = Report::default();
+        self.check_body("the script", &[], exprs, Scope::default(), &mut report);
+        if report.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(InterpreterError::AffineViolation(report.violations))
+        }
+    }
+
+    // returns what the body returns, when that is owned
+    fn check_body(
+        &mut self,
+        context: &str,
+        params: &[String],
+        body: &[Rc<Expr>],
+        mut scope: Scope,
+        report: &mut Report,
+    ) -> Option<Origin> {
+        for param in params {
+            scope.owned.remove(param);
+            scope.returns.remove(param);
+            scope.locals.insert(param.clone());
+        }
+
+        let mut returned = None;
+        for (i, expr) in body.iter().enumerate() {
+            let site = expr.to_string();
+            let value = self.check_expr(expr, &site, context, &mut scope, report);
+            if i + 1 == body.len() {
+                returned = value;
+            } else if let Some((Ownership::Linear, origin)) = value {
+                report.violations.push(format!(
+                    "a linear value ({}) is dropped without being used in {}: `{}`",
+                    origin, context, site
+                ));
+            }
+        }
+
+        for (name, owned) in &scope.owned {
+            if owned.ownership == Ownership::Linear && owned.used.is_none() {
+                report.violations.push(format!(
+                    "`{}` is linear ({}) but is never used in {}",
+                    name, owned.origin, context
+                ));
+            }
+        }
+        returned
+    }
+
+    // `site` is the outermost expression being checked, reported as where uses happen; calls
+    // report themselves instead
+    fn check_expr(
+        &mut self,
+        expr: &Expr,
+        site: &str,
+        context: &str,
+        scope: &mut Scope,
+        report: &mut Report,
+    ) -> Option<Origin> {
+        match expr {
+            Expr::Primitive(_)
+            | Expr::InfixOp(..)
+            | Expr::NotationDecl(..)
+            | Expr::FFIDecl(..)
+            | Expr::ModuleDecl(..) => None,
+            Expr::Array(elements) => elements.iter().fold(None, |owned, element| {
+                let element = self.check_expr(element, site, context, scope, report);
+                owned.or(element)
+            }),
+            Expr::Variable(name) => use_name(name, site, context, scope, report),
+            Expr::Record(base, fields) => {
+                for expr in base.iter().chain(fields.iter().map(|(_, value)| value)) {
+                    self.check_expr(expr, site, context, scope, report);
+                }
+                None
+            }
+            // reading a field of an owned record uses the record
+            Expr::Field(record, _) => {
+                self.check_expr(record, site, context, scope, report);
+                None
+            }
+            Expr::FunctionCall(func, args, kwargs) => {
+                let call = expr.to_string();
+                for arg in args.iter().chain(kwargs.iter().map(|(_, arg)| arg)) {
+                    self.check_expr(arg, &call, context, scope, report);
+                }
+                match func.as_ref() {
+                    Expr::Variable(name) if scope.owned.contains_key(name) => {
+                        use_name(name, &call, context, scope, report);
+                        scope.returns.get(name).cloned()
+                    }
+                    Expr::Variable(name) if scope.returns.contains_key(name) => {
+                        scope.returns.get(name).cloned()
+                    }
+                    Expr::Variable(name) if scope.locals.contains(name) => None,
+                    Expr::Variable(name) => self.returned_by(name, report),
+                    func => {
+                        self.check_expr(func, &call, context, scope, report);
+                        match func {
+                            // `m.f` of a module bound by `use`, unless `m` is bound here
+                            Expr::Field(module, _) if !scope.binds(module) => {
+                                let value = self.member(func)?;
+                                self.returned_by_value(&value, report)
+                            }
+                            _ => None,
+                        }
+                    }
+                }
+            }
+            Expr::Return(expr) => self.check_expr(expr, site, context, scope, report),
+            Expr::Assignment(name, expr) => {
+                let value = self.check_expr(expr, site, context, scope, report);
+                if let Some(owned) = scope.owned.remove(name)
+                    && owned.ownership == Ownership::Linear
+                    && owned.used.is_none()
+                {
+                    report.violations.push(format!(
+                        "`{}` is linear ({}) but is rebound without being used in {}: `{}`",
+                        name, owned.origin, context, site
+                    ));
+                }
+                scope.returns.remove(name);
+                match value {
+                    Some((ownership, origin)) => {
+                        scope.owned.insert(
+                            name.clone(),
+                            Owned {
+                                ownership,
+                                origin,
+                                used: None,
+                                reported: false,
+                            },
+                        );
+                    }
+                    None => {
+                        scope.locals.insert(name.clone());
+                    }
+                }
+                None
+            }
+            Expr::FunctionDef(name, params, body, _) => {
+                self.check_function(name, params, body, context, scope, report);
+                None
+            }
+            Expr::Try(body, clauses) => {
+                for expr in body {
+                    self.check_expr(expr, &expr.to_string(), context, scope, report);
+                }
+                // at most one clause runs: a value is used after the `try` if any clause uses it
+                let after_body = scope.clone();
+                for clause in clauses {
+                    let mut branch = after_body.clone();
+                    if let Some(binding) = &clause.binding {
+                        branch.owned.remove(binding);
+                        branch.locals.insert(binding.clone());
+                    }
+                    for expr in &clause.body {
+                        self.check_expr(expr, &expr.to_string(), context, &mut branch, report);
+                    }
+                    for (name, owned) in branch.owned {
+                        match scope.owned.get_mut(&name) {
+                            Some(merged) => {
+                                merged.used = merged.used.take().or(owned.used);
+                                merged.reported |= owned.reported;
+                            }
+                            None => {
+                                scope.owned.insert(name, owned);
+                            }
+                        }
+                    }
+                    scope.returns.extend(branch.returns);
+                    scope.locals.extend(branch.locals);
+                }
+                None
+            }
+        }
+    }
+
+    // a nested function is checked on its own, starting with the owned values it captures
+    fn check_function(
+        &mut self,
+        name: &str,
+        params: &[String],
+        body: &[Rc<Expr>],
+        context: &str,
+        scope: &mut Scope,
+        report: &mut Report,
+    ) {
+        let mut mentioned = HashSet::new();
+        for expr in body {
+            mentions(expr, &mut mentioned);
+        }
+        let captured: Vec<String> = scope
+            .owned
+            .keys()
+            .filter(|owned| mentioned.contains(*owned) && !params.contains(*owned))
+            .cloned()
+            .collect();
+
+        let site = format!("fun {}", name);
+        let mut inner = Scope {
+            returns: scope.returns.clone(),
+            ..Scope::default()
+        };
+        let mut ownership = None;
+        for owned in &captured {
+            use_name(owned, &site, context, scope, report);
+            let mut fresh = scope.owned[owned].clone();
+            fresh.used = None;
+            fresh.reported = false;
+            ownership = match (ownership, fresh.ownership) {
+                (Some(Ownership::Linear), _) | (_, Ownership::Linear) => Some(Ownership::Linear),
+                _ => Some(Ownership::Affine),
+            };
+            inner.owned.insert(owned.clone(), fresh);
+        }
+
+        let returned = self.check_body(&site, params, body, inner, report);
+        scope.locals.remove(name);
+        match returned {
+            Some(returned) => scope.returns.insert(name.to_string(), returned),
+            None => scope.returns.remove(name),
+        };
+        match ownership {
+            Some(ownership) => {
+                scope.owned.insert(
+                    name.to_string(),
+                    Owned {
+                        ownership,
+                        origin: format!("{}, which captures {}", site, captured.join(", ")),
+                        used: None,
+                        reported: false,
+                    },
+                );
+            }
+            None => {
+                scope.owned.remove(name);
+            }
+        }
+    }
+
+    // what calling a global name returns, when that is owned
+    fn returned_by(&mut self, name: &str, report: &mut Report) -> Option<Origin> {
+        let value = self.lookup(name).ok()?;
+        self.returned_by_value(&value, report)
+    }
+
+    fn returned_by_value(&mut self, value: &Value, report: &mut Report) -> Option<Origin> {
+        match value {
+            Value::Ffi(function) => self
+                .ffi
+                .ownership_of(function)
+                .map(|ownership| (ownership, format!("returned by {}", function))),
+            Value::Glob(providers) => providers.iter().find_map(|function| {
+                self.ffi
+                    .ownership_of(function)
+                    .map(|ownership| (ownership, format!("returned by {}", function)))
+            }),
+            Value::PartialApplication(func, _) => self.returned_by_value(func, report),
+            Value::Function(name, params, body, closure_env, ..) => {
+                let key = format!("{:p}.{}", Rc::as_ptr(closure_env), name);
+                if let Some(returned) = report.returns.get(&key) {
+                    return returned.clone();
+                }
+                // a recursive call returns nothing owned until shown otherwise
+                report.returns.insert(key.clone(), None);
+
+                // violations inside are reported where the function is checked, not here
+                let violations = std::mem::take(&mut report.violations);
+                let saved_env = std::mem::replace(&mut self.env, Rc::clone(closure_env));
+                let context = format!("fun {}", name);
+                let returned = self
+                    .check_body(&context, params, body, Scope::default(), report)
+                    .map(|(ownership, _)| (ownership, format!("returned by {}", name)));
+                self.env = saved_env;
+                report.violations = violations;
+
+                report.returns.insert(key, returned.clone());
+                returned
+            }
+            Value::Primitive(_) | Value::Module(_) | Value::Pending(_) | Value::Builtin(_) => None,
+        }
+    }
+}
+
+// marks an owned name used at `site`, reporting a second use once
+fn use_name(
+    name: &str,
+    site: &str,
+    context: &str,
+    scope: &mut Scope,
+    report: &mut Report,
+) -> Option<Origin> {
+    let owned = scope.owned.get_mut(name)?;
+    match &owned.used {
+        None => owned.used = Some(site.to_string()),
+        Some(first) if !owned.reported => {
+            report.violations.push(format!(
+                "`{}` is {} ({}) but is used twice in {}: first in `{}`, then in `{}`",
+                name, owned.ownership, owned.origin, context, first, site
+            ));
+            owned.reported = true;
+        }
+        Some(_) => {}
+    }
+    Some((owned.ownership, owned.origin.clone()))
+}
+
+// every name an expression mentions, including inside nested functions
+fn mentions(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Expr::Array(elements) => elements.iter().for_each(|e| mentions(e, names)),
+        Expr::FunctionCall(func, args, kwargs) => {
+            mentions(func, names);
+            args.iter().for_each(|arg| mentions(arg, names));
+            kwargs.iter().for_each(|(_, arg)| mentions(arg, names));
+        }
+        Expr::Return(expr) | Expr::Assignment(_, expr) | Expr::Field(expr, _) => {
+            mentions(expr, names)
+        }
+        Expr::Record(base, fields) => base
+            .iter()
+            .chain(fields.iter().map(|(_, value)| value))
+            .for_each(|e| mentions(e, names)),
+        Expr::FunctionDef(_, _, body, _) => body.iter().for_each(|e| mentions(e, names)),
+        Expr::Try(body, clauses) => {
+            body.iter().for_each(|e| mentions(e, names));
+            for clause in clauses {
+                clause.body.iter().for_each(|e| mentions(e, names));
+            }
+        }
+        Expr::Primitive(_)
+        | Expr::InfixOp(..)
+        | Expr::NotationDecl(..)
+        | Expr::FFIDecl(..)
+        | Expr::ModuleDecl(..) => {}
+    }
+}
@@ -0,0 +1,183 @@
+// interpreter/builtin.rs
+//
+// the functions an interpolated string is desugared to: `f"{n:.2f} words"` calls
+// `$concat([$format(n, ".2f"), " words"])`. a string is inserted as it is, any other value as it
+// is displayed; a spec pads, aligns and rounds it.
+
+use super::evaluator::{force, InterpreterError};
+use crate::syntax::{Builtin, FormatSpec, Primitive, Value};
+
+pub(super) fn call(builtin: Builtin, args: Vec<Value>) -> Result<Value, InterpreterError> {
+    let args = args.into_iter().map(force).collect::<Result<Vec<_>, _>>()?;
+    let text = match (builtin, args.as_slice()) {
+        (Builtin::Format, [value, Value::Primitive(Primitive::String(spec))]) => {
+            format(value, spec)?
+        }
+        (Builtin::Concat, [Value::Primitive(Primitive::Array(parts))]) => parts
+            .iter()
+            .map(|part| match part {
+                Primitive::String(s) => s.clone(),
+                part => part.to_string(),
+            })
+            .collect(),
+        _ => {
+            return Err(InterpreterError::NotReachable(format!(
+                "{} is only called by interpolated strings",
+                builtin.name()
+            )));
+        }
+    };
+    Ok(Value::Primitive(Primitive::String(text)))
+}
+
+fn format(value: &Value, text_spec: &str) -> Result<String, InterpreterError> {
+    let Some(spec) = FormatSpec::parse(text_spec) else {
+        return Err(InterpreterError::TypeMismatch(format!(
+            "Invalid format spec '{}'",
+            text_spec
+        )));
+    };
+    let number = match value {
+        Value::Primitive(Primitive::Int(i)) => Some(*i as f64),
+        Value::Primitive(Primitive::BigInt(i)) => i.to_string().parse().ok(),
+        Value::Primitive(Primitive::Float(f)) => Some(*f),
+        _ => None,
+    };
+    let text = match (number, spec.kind, spec.precision) {
+        (None, Some(_), _) => {
+            return Err(InterpreterError::TypeMismatch(format!(
+                "Format spec '{}' needs a number, got {}",
+                text_spec, value
+            )));
+        }
+        (Some(n), Some('f'), precision) => format!("{:.*}", precision.unwrap_or(6), n),
+        (Some(n), Some('e'), precision) => format!("{:.*e}", precision.unwrap_or(6), n),
+        (Some(n), Some('%'), precision) => format!("{:.*}%", precision.unwrap_or(6), n * 100.0),
+        (Some(n), _, Some(precision)) => format!("{:.*}", precision, n),
+        (None, _, Some(precision)) => display(value).chars().take(precision).collect(),
+        _ => display(value),
+    };
+    let text = match number {
+        Some(n) if spec.sign && n >= 0.0 => format!("+{}", text),
+        _ => text,
+    };
+    Ok(pad(text, &spec, number.is_some()))
+}
+
+// strings go in without their quotes
+fn display(value: &Value) -> String {
+    match value {
+        Value::Primitive(Primitive::String(s)) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+// numbers are aligned right unless told otherwise, and `0` pads them after their sign
+fn pad(text: String, spec: &FormatSpec, number: bool) -> String {
+    let missing = spec.width.saturating_sub(text.chars().count());
+    if missing == 0 {
+        return text;
+    }
+    if number && spec.zero && spec.align.is_none() {
+        let digits = text.trim_start_matches(['+', '-']);
+        let sign = &text[..text.len() - digits.len()];
+        return format!("{}{}{}", sign, "0".repeat(missing), digits);
+    }
+    let fill = |n: usize| spec.fill.to_string().repeat(n);
+    match spec.align.unwrap_or(if number { '>' } else { '<' }) {
+        '>' => format!("{}{}", fill(missing), text),
+        '^' => format!(
+            "{}{}{}",
+            fill(missing / 2),
+            text,
+            fill(missing - missing / 2)
+        ),
+        _ => format!("{}{}", text, fill(missing)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(value: Primitive, spec: &str) -> Result<String, InterpreterError> {
+        let spec = Value::Primitive(Primitive::String(spec.to_string()));
+        match call(Builtin::Format, vec![Value::Primitive(value), spec])? {
+            Value::Primitive(Primitive::String(text)) => Ok(text),
+            other => panic!("$format returned {}", other),
+        }
+    }
+
+    fn text(s: &str) -> Primitive {
+        Primitive::String(s.to_string())
+    }
+
+    #[test]
+    fn fill_and_align() {
+        assert_eq!(formatted(text("mid"), "*^9").unwrap(), "***mid***");
+        assert_eq!(formatted(text("mid"), "^8").unwrap(), "  mid   ");
+        assert_eq!(formatted(text("ab"), "5").unwrap(), "ab   ");
+        assert_eq!(formatted(Primitive::Int(42), "5").unwrap(), "   42");
+        assert_eq!(formatted(Primitive::Int(42), "-<5").unwrap(), "42---");
+        assert_eq!(formatted(Primitive::Int(42), "<<5").unwrap(), "42<<<");
+        // too wide already
+        assert_eq!(formatted(text("abcdef"), ">3").unwrap(), "abcdef");
+    }
+
+    #[test]
+    fn sign_and_zero_padding() {
+        assert_eq!(
+            formatted(Primitive::Float(3.25), "+08.2f").unwrap(),
+            "+0003.25"
+        );
+        assert_eq!(
+            formatted(Primitive::Float(-2.5), "08.1f").unwrap(),
+            "-00002.5"
+        );
+        assert_eq!(formatted(Primitive::Int(-1), "+").unwrap(), "-1");
+        assert_eq!(formatted(Primitive::Int(7), "+05").unwrap(), "+0007");
+        // an alignment wins over `0`
+        assert_eq!(formatted(Primitive::Int(7), ">05").unwrap(), "    7");
+    }
+
+    #[test]
+    fn number_types() {
+        assert_eq!(
+            formatted(Primitive::Float(1.0 / 3.0), "f").unwrap(),
+            "0.333333"
+        );
+        assert_eq!(formatted(Primitive::Int(2), ".3f").unwrap(), "2.000");
+        assert_eq!(formatted(Primitive::Float(0.256), ".1%").unwrap(), "25.6%");
+        assert_eq!(formatted(Primitive::Int(1), "%").unwrap(), "100.000000%");
+        assert_eq!(
+            formatted(Primitive::Float(1234.5), ".2e").unwrap(),
+            "1.23e3"
+        );
+        assert_eq!(formatted(Primitive::Float(2.675), ".1").unwrap(), "2.7");
+        assert!(formatted(text("x"), ".2f").is_err());
+    }
+
+    #[test]
+    fn precision_cuts_text() {
+        assert_eq!(formatted(text("abcdef"), ".3").unwrap(), "abc");
+        assert_eq!(formatted(text("héllo"), ".2").unwrap(), "hé");
+    }
+
+    #[test]
+    fn concat_shows_strings_without_quotes() {
+        let parts = Primitive::Array(vec![text("n = "), Primitive::Int(3), text("!")]);
+        match call(Builtin::Concat, vec![Value::Primitive(parts)]).unwrap() {
+            Value::Primitive(Primitive::String(text)) => assert_eq!(text, "n = 3!"),
+            other => panic!("$concat returned {}", other),
+        }
+    }
+
+    #[test]
+    fn widths_are_capped() {
+        assert!(FormatSpec::parse("4096").is_some());
+        assert!(FormatSpec::parse("4097").is_none());
+        assert!(FormatSpec::parse(".4097f").is_none());
+        assert!(FormatSpec::parse("99999999999999999999999").is_none());
+        assert!(formatted(Primitive::Int(1), "1000000000").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+// interpreter/check.rs
+//
+// `galois check`: finds type mismatches before a script runs. types come from annotations on
+// galois functions (`fun f(x: int) -> string`) and from signatures of foreign declarations;
+// everything else is `any`, which is consistent with every type, so unannotated code checks
+// as it runs. the result of a function without a declared one is inferred from its body.
+
+use super::modules::{is_galois_module, ModuleLoader};
+use crate::syntax::{Builtin, Expr, Primitive, Type};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// what a scope knows about its names
+#[derive(Clone, Default)]
+struct Scope {
+    types: HashMap<String, Type>,
+    params: HashMap<String, Vec<String>>, // galois functions -> their parameter names
+    modules: HashMap<String, String>,     // bound by `use`, name -> module
+}
+
+pub struct Checker<'a> {
+    modules: &'a mut ModuleLoader,
+    exports: HashMap<String, Scope>, // galois module -> what its top level binds
+    mismatches: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(modules: &'a mut ModuleLoader) -> Self {
+        Checker {
+            modules,
+            exports: HashMap::new(),
+            mismatches: Vec::new(),
+        }
+    }
+
+    // every mismatch in the script and the galois modules it imports
+    pub fn check(mut self, exprs: &[Rc<Expr>]) -> Vec<String> {
+        self.check_body("the script", exprs, &mut Scope::default());
+        self.mismatches
+    }
+
+    // the type of the body is that of its last expression
+    fn check_body(&mut self, context: &str, body: &[Rc<Expr>], scope: &mut Scope) -> Type {
+        // functions can be called before they are defined
+        for expr in body {
+            if let Expr::FunctionDef(name, params, _, annotations) = &**expr {
+                scope
+                    .types
+                    .insert(name.clone(), annotations.function_type());
+                scope.params.insert(name.clone(), params.clone());
+            }
+        }
+        body.iter()
+            .fold(Type::None, |_, expr| self.check_expr(context, expr, scope))
+    }
+
+    fn check_expr(&mut self, context: &str, expr: &Expr, scope: &mut Scope) -> Type {
+        match expr {
+            Expr::Primitive(p) => primitive_type(p),
+            Expr::Array(elements) => {
+                let types: Vec<Type> = elements
+                    .iter()
+                    .map(|element| self.check_expr(context, element, scope))
+                    .collect();
+                Type::Array(Box::new(join_all(types)))
+            }
+            Expr::Variable(name) => match scope.types.get(name) {
+                Some(ty) => ty.clone(),
+                None => self
+                    .exported(expr, scope)
+                    .or_else(|| Builtin::named(name).map(Builtin::function_type))
+                    .unwrap_or_default(),
+            },
+            Expr::Record(base, fields) => {
+                if let Some(base) = base {
+                    let ty = self.check_expr(context, base, scope);
+                    if !consistent(&ty, &Type::Record) {
+                        self.mismatch(
+                            context,
+                            format!("`{}` updates {}, which is not a record", expr, ty),
+                        );
+                    }
+                }
+                for (_, value) in fields {
+                    self.check_expr(context, value, scope);
+                }
+                Type::Record
+            }
+            Expr::Field(record, field) => match self.exported(expr, scope) {
+                Some(ty) => ty,
+                None => {
+                    let ty = self.check_expr(context, record, scope);
+                    self.check_field(context, expr, &ty, field)
+                }
+            },
+            Expr::FunctionDef(name, params, body, annotations) => {
+                let mut inner = scope.clone();
+                for (param, ty) in params.iter().zip(
+                    annotations
+                        .params
+                        .iter()
+                        .chain(std::iter::repeat(&Type::Any)),
+                ) {
+                    inner.types.insert(param.clone(), ty.clone());
+                    inner.params.remove(param);
+                    inner.modules.remove(param);
+                }
+                let context = format!("fun {}", name);
+                let body_type = self.check_body(&context, body, &mut inner);
+
+                let ret = if annotations.ret == Type::Any {
+                    body_type
+                } else {
+                    if !consistent(&body_type, &annotations.ret) {
+                        self.mismatch(
+                            &context,
+                            format!(
+                                "returns {}, but is declared to return {}",
+                                body_type, annotations.ret
+                            ),
+                        );
+                    }
+                    annotations.ret.clone()
+                };
+                let ty = Type::Function(annotations.params.clone(), Box::new(ret));
+                scope.types.insert(name.clone(), ty.clone());
+                scope.params.insert(name.clone(), params.clone());
+                ty
+            }
+            Expr::FunctionCall(func, args, kwargs) => {
+                let func_type = self.check_expr(context, func, scope);
+                let arg_types: Vec<Type> = args
+                    .iter()
+                    .map(|arg| self.check_expr(context, arg, scope))
+                    .collect();
+                let kwarg_types: Vec<(&String, Type)> = kwargs
+                    .iter()
+                    .map(|(name, arg)| (name, self.check_expr(context, arg, scope)))
+                    .collect();
+                let names = self.params_of(func, scope);
+                self.check_call(context, expr, func_type, arg_types, kwarg_types, names)
+            }
+            Expr::Return(expr) => self.check_expr(context, expr, scope),
+            Expr::Assignment(name, expr) => {
+                let ty = self.check_expr(context, expr, scope);
+                scope.types.insert(name.clone(), ty.clone());
+                scope.params.remove(name);
+                scope.modules.remove(name);
+                ty
+            }
+            Expr::FFIDecl(module, name, alias, signature, _) => {
+                let import_name = alias.as_ref().unwrap_or(name);
+                if is_galois_module(module) {
+                    let exports = self.exports_of(module);
+                    // (bound name, function) pairs; local names shadow glob imports
+                    let imported: Vec<(String, String)> = if name == "*" {
+                        exports
+                            .types
+                            .keys()
+                            .filter(|function| {
+                                !function.starts_with('_') && !scope.types.contains_key(*function)
+                            })
+                            .map(|function| (function.clone(), function.clone()))
+                            .collect()
+                    } else {
+                        vec![(import_name.clone(), name.clone())]
+                    };
+                    for (bound, function) in imported {
+                        let ty = exports.types.get(&function).cloned().unwrap_or_default();
+                        match exports.params.get(&function) {
+                            Some(params) => scope.params.insert(bound.clone(), params.clone()),
+                            None => scope.params.remove(&bound),
+                        };
+                        scope.types.insert(bound, ty);
+                    }
+                } else if name != "*" {
+                    // a foreign function that says nothing about its types takes and returns any
+                    let ty = match signature {
+                        Some(signature) => Type::Function(
+                            signature.params.clone(),
+                            Box::new(signature.ret.clone().unwrap_or_default()),
+                        ),
+                        None => Type::Any,
+                    };
+                    scope.types.insert(import_name.clone(), ty);
+                    scope.params.remove(import_name);
+                }
+                Type::None
+            }
+            Expr::ModuleDecl(module, alias) => {
+                let name = alias
+                    .as_ref()
+                    .unwrap_or(module)
+                    .rsplit('.')
+                    .next()
+                    .unwrap_or(module);
+                scope.modules.insert(name.to_string(), module.clone());
+                scope.types.remove(name);
+                Type::None
+            }
+            Expr::InfixOp(..) | Expr::NotationDecl(..) => Type::Any,
+            Expr::Try(body, clauses) => {
+                let mut ty = self.check_body(context, body, scope);
+                for clause in clauses {
+                    let mut inner = scope.clone();
+                    if let Some(binding) = &clause.binding {
+                        inner.types.insert(binding.clone(), Type::String);
+                    }
+                    ty = join(ty, self.check_body(context, &clause.body, &mut inner));
+                }
+                ty
+            }
+        }
+    }
+
+    fn check_call(
+        &mut self,
+        context: &str,
+        call: &Expr,
+        func_type: Type,
+        args: Vec<Type>,
+        kwargs: Vec<(&String, Type)>,
+        names: Option<Vec<String>>,
+    ) -> Type {
+        let (params, ret) = match func_type {
+            Type::Any => return Type::Any,
+            Type::Function(params, ret) => (params, ret),
+            other => {
+                self.mismatch(
+                    context,
+                    format!("`{}` calls {}, which is not a function", call, other),
+                );
+                return Type::Any;
+            }
+        };
+        if args.len() > params.len() {
+            self.mismatch(
+                context,
+                format!(
+                    "`{}` passes {} arguments to a function taking {}",
+                    call,
+                    args.len(),
+                    params.len()
+                ),
+            );
+            return *ret;
+        }
+
+        let mut bound = vec![false; params.len()];
+        for (i, (arg, param)) in args.iter().zip(&params).enumerate() {
+            bound[i] = true;
+            if !consistent(arg, param) {
+                self.mismatch(
+                    context,
+                    format!(
+                        "`{}`: argument {} must be {}, got {}",
+                        call,
+                        i + 1,
+                        param,
+                        arg
+                    ),
+                );
+            }
+        }
+        for (name, arg) in &kwargs {
+            // named arguments of foreign functions are not declared
+            let Some(names) = &names else {
+                return Type::Any;
+            };
+            match names.iter().position(|param| param == *name) {
+                Some(i) => {
+                    bound[i] = true;
+                    let param = params.get(i).unwrap_or(&Type::Any);
+                    if !consistent(arg, param) {
+                        self.mismatch(
+                            context,
+                            format!("`{}`: `{}` must be {}, got {}", call, name, param, arg),
+                        );
+                    }
+                }
+                None => self.mismatch(
+                    context,
+                    format!("`{}`: the function has no parameter named `{}`", call, name),
+                ),
+            }
+        }
+
+        // too few arguments leave a function of the others
+        let rest: Vec<Type> = params
+            .into_iter()
+            .zip(bound)
+            .filter(|(_, bound)| !bound)
+            .map(|(param, _)| param)
+            .collect();
+        if rest.is_empty() {
+            *ret
+        } else {
+            Type::Function(rest, ret)
+        }
+    }
+
+    // fields have no declared types
+    fn check_field(&mut self, context: &str, expr: &Expr, record: &Type, field: &str) -> Type {
+        if !consistent(record, &Type::Record) {
+            self.mismatch(
+                context,
+                format!(
+                    "`{}` reads field `{}` of {}, which is not a record",
+                    expr, field, record
+                ),
+            );
+        }
+        Type::Any
+    }
+
+    // `m.f` reaches into a module bound by `use`, `galois.m.f` into a galois module
+    fn exported(&mut self, expr: &Expr, scope: &Scope) -> Option<Type> {
+        let (module, function) = self.qualified(expr, scope)?;
+        Some(
+            self.exports_of(&module)
+                .types
+                .get(&function)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn params_of(&mut self, func: &Expr, scope: &Scope) -> Option<Vec<String>> {
+        if let Expr::Variable(name) = func
+            && scope.types.contains_key(name)
+        {
+            return scope.params.get(name).cloned();
+        }
+        let (module, function) = self.qualified(func, scope)?;
+        self.exports_of(&module).params.get(&function).cloned()
+    }
+
+    // the galois module and function `m.f` refers to, or a fully qualified name left behind by
+    // an imported notation
+    fn qualified(&self, expr: &Expr, scope: &Scope) -> Option<(String, String)> {
+        let (module, function) = match expr {
+            Expr::Field(head, function) => match head.as_ref() {
+                Expr::Variable(head) if !scope.types.contains_key(head) => {
+                    (scope.modules.get(head)?.clone(), function.clone())
+                }
+                _ => return None,
+            },
+            Expr::Variable(name) => {
+                let (module, function) = name.rsplit_once('.')?;
+                (module.to_string(), function.to_string())
+            }
+            _ => return None,
+        };
+        is_galois_module(&module).then_some((module, function))
+    }
+
+    // galois modules are checked once, the first time they are imported
+    fn exports_of(&mut self, module_path: &str) -> Scope {
+        if let Some(exports) = self.exports.get(module_path) {
+            return exports.clone();
+        }
+        // an import cycle sees an empty module
+        self.exports
+            .insert(module_path.to_string(), Scope::default());
+        let Ok(module) = self.modules.load(module_path) else {
+            return Scope::default();
+        };
+        let mut scope = Scope::default();
+        self.check_body(
+            &format!("module {}", module_path),
+            &module.exprs,
+            &mut scope,
+        );
+        self.exports.insert(module_path.to_string(), scope.clone());
+        scope
+    }
+
+    fn mismatch(&mut self, context: &str, message: String) {
+        self.mismatches.push(format!("in {}: {}", context, message));
+    }
+}
+
+fn primitive_type(p: &Primitive) -> Type {
+    match p {
+        Primitive::Int(_) | Primitive::BigInt(_) => Type::Int,
+        Primitive::Float(_) => Type::Float,
+        Primitive::String(_) => Type::String,
+        Primitive::Bool(_) => Type::Bool,
+        Primitive::None => Type::None,
+        Primitive::Array(elements) => Type::Array(Box::new(join_all(
+            elements.iter().map(primitive_type).collect(),
+        ))),
+        Primitive::Record(_) => Type::Record,
+    }
+}
+
+// whether a value of type `actual` may be used where `expected` is; an int is a float too
+fn consistent(actual: &Type, expected: &Type) -> bool {
+    match (actual, expected) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (actual, expected) if actual.is_integer() && expected.is_integer() => true,
+        (actual, Type::Float) if actual.is_integer() => true,
+        (Type::Array(actual), Type::Array(expected)) => consistent(actual, expected),
+        (Type::Function(actual_params, actual_ret), Type::Function(params, ret)) => {
+            actual_params.len() == params.len()
+                && actual_params
+                    .iter()
+                    .zip(params)
+                    .all(|(actual, expected)| consistent(expected, actual))
+                && consistent(actual_ret, ret)
+        }
+        (actual, expected) => actual == expected,
+    }
+}
+
+// the most precise type both values have
+fn join(a: Type, b: Type) -> Type {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (a, b) if a.is_integer() && b.is_integer() => Type::Int,
+        (a, Type::Float) | (Type::Float, a) if a.is_integer() => Type::Float,
+        (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(join(*a, *b))),
+        _ => Type::Any,
+    }
+}
+
+fn join_all(types: Vec<Type>) -> Type {
+    let mut types = types.into_iter();
+    match types.next() {
+        Some(first) => types.fold(first, join),
+        None => Type::Any,
+    }
+}
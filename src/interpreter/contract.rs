@@ -0,0 +1,165 @@
+// interpreter/contract.rs
+//
+// runtime contracts: the annotations of a galois function and the signature of a foreign one
+// are checked on every call, so a value of the wrong type stops the call that received or
+// returned it rather than some later one. `any` is not checked. a function type only checks
+// that the value is a function taking that many arguments, when that is known.
+//
+// under `--parallel` an annotated argument of a galois function is waited for; the arguments
+// and result of a foreign call are checked once they resolve, without holding up the script.
+
+use super::evaluator::{force, InterpreterError};
+use crate::ffi::{FFIError, Promise};
+use crate::syntax::{Primitive, Signature, Type, Value};
+use futures::FutureExt;
+
+// the arguments bound to a galois function's parameters, annotated ones forced and checked
+pub(super) fn arguments(
+    function: &str,
+    bound: Vec<(String, Value)>,
+    types: &[Type],
+) -> Result<Vec<(String, Value)>, InterpreterError> {
+    bound
+        .into_iter()
+        .zip(types.iter().chain(std::iter::repeat(&Type::Any)))
+        .map(|((param, value), ty)| {
+            if *ty == Type::Any {
+                return Ok((param, value));
+            }
+            let value = force(value)?;
+            if !conforms(&value, ty) {
+                return Err(InterpreterError::TypeMismatch(argument_mismatch(
+                    function,
+                    &format!("parameter '{}'", param),
+                    ty,
+                    &describe(&value),
+                )));
+            }
+            Ok((param, value))
+        })
+        .collect()
+}
+
+// what a galois function returned, forced and checked when it declares its result
+pub(super) fn result(function: &str, value: Value, ty: &Type) -> Result<Value, InterpreterError> {
+    if *ty == Type::Any {
+        return Ok(value);
+    }
+    let value = force(value)?;
+    if !conforms(&value, ty) {
+        return Err(InterpreterError::TypeMismatch(result_mismatch(
+            function,
+            ty,
+            &describe(&value),
+        )));
+    }
+    Ok(value)
+}
+
+// positional arguments of a foreign call, which are primitives by now
+pub(super) fn foreign_arguments(
+    function: &str,
+    args: &[Primitive],
+    signature: &Signature,
+) -> Result<(), InterpreterError> {
+    for (i, (arg, ty)) in args.iter().zip(&signature.params).enumerate() {
+        if !ty.admits(arg) {
+            return Err(InterpreterError::TypeMismatch(argument_mismatch(
+                function,
+                &format!("argument {}", i + 1),
+                ty,
+                &arg.to_string(),
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn foreign_result(
+    function: &str,
+    value: &Primitive,
+    signature: &Signature,
+) -> Result<(), InterpreterError> {
+    match &signature.ret {
+        Some(ty) if !ty.admits(value) => Err(InterpreterError::TypeMismatch(result_mismatch(
+            function,
+            ty,
+            &value.to_string(),
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// a pending argument or result of a foreign call, failing once it resolves to the wrong type.
+// `argument` is the position of an argument, none for the result.
+pub(super) fn checked(
+    promise: Promise,
+    function: &str,
+    argument: Option<usize>,
+    ty: &Type,
+) -> Promise {
+    if *ty == Type::Any {
+        return promise;
+    }
+    let function = function.to_string();
+    let ty = ty.clone();
+    promise
+        .map(move |value| {
+            let value = value?;
+            if ty.admits(&value) {
+                return Ok(value);
+            }
+            let got = value.to_string();
+            Err(FFIError::TypeMismatch(match argument {
+                Some(i) => argument_mismatch(&function, &format!("argument {}", i + 1), &ty, &got),
+                None => result_mismatch(&function, &ty, &got),
+            }))
+        })
+        .boxed()
+        .shared()
+}
+
+fn conforms(value: &Value, ty: &Type) -> bool {
+    match (value, ty) {
+        (_, Type::Any) => true,
+        (Value::Primitive(p), ty) => ty.admits(p),
+        (Value::Function(..) | Value::PartialApplication(..), Type::Function(params, _)) => {
+            arity(value).is_none_or(|arity| arity == params.len())
+        }
+        (Value::Ffi(_) | Value::Glob(_) | Value::Builtin(_), Type::Function(..)) => true,
+        _ => false,
+    }
+}
+
+// how many arguments a function still takes, when it is a galois one
+fn arity(value: &Value) -> Option<usize> {
+    match value {
+        Value::Function(_, params, ..) => Some(params.len()),
+        Value::PartialApplication(func, args) => {
+            arity(func).map(|arity| arity.saturating_sub(args.len()))
+        }
+        _ => None,
+    }
+}
+
+// functions are named rather than printed with their bodies
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Function(name, ..) => format!("function {}", name),
+        value => value.to_string(),
+    }
+}
+
+fn argument_mismatch(function: &str, param: &str, ty: &Type, got: &str) -> String {
+    format!(
+        "Function '{}' expects {} for {}, got {}",
+        function, ty, param, got
+    )
+}
+
+fn result_mismatch(function: &str, ty: &Type, got: &str) -> String {
+    format!(
+        "Function '{}' is declared to return {}, but returned {}",
+        function, ty, got
+    )
+}
@@ -1,15 +1,46 @@
 // interpreter/evaluator.rs
 
+use super::builtin;
+use super::contract;
+use super::modules::{is_galois_module, ModuleLoader};
 use crate::debug::DebugPrinter;
-use crate::ffi::{FFIBackend, FFIProtocol};
-use crate::syntax::{Environment, Expr, Primitive, Value};
+use crate::ffi::cache::Cache;
+use crate::ffi::effect::Effect;
+use crate::ffi::limit::Limits;
+use crate::ffi::mock::Fixtures;
+use crate::ffi::ownership::Ownership;
+use crate::ffi::record::{Recorder, Recording};
+use crate::ffi::{resolved, FFIBackend, FFIError, FFIProtocol, ForeignException, Promise};
+use crate::syntax::{Attribute, Builtin, Environment, Expr, Primitive, Signature, Value};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::rc::Rc;
 
 pub struct Interpreter {
-    env: Rc<RefCell<Environment>>,
+    pub(super) env: Rc<RefCell<Environment>>,
     debug: DebugPrinter,
-    ffi: FFIBackend,
+    pub(super) ffi: FFIBackend,
+    modules: ModuleLoader,
+    module_envs: HashMap<String, Rc<RefCell<Environment>>>, // galois module -> its top level
+    imports: HashMap<String, String>, // explicitly imported name -> qualified origin
+    signatures: HashMap<String, Signature>, // declared for foreign functions, checked on calls
+    parallel: bool,
+    affine: bool,
+    outstanding: Vec<Promise>, // foreign calls started and not yet known to have succeeded
+}
+
+// how a script is run, beyond what it says itself
+#[derive(Default)]
+pub struct Options {
+    pub parallel: bool,
+    pub schedule_seed: Option<u64>, // delays parallel calls at random, to test their ordering
+    pub affine: bool,               // check affine and linear values before running
+    pub mock: Option<Fixtures>,     // canned foreign functions instead of the real ones
+    pub record: Option<Recorder>,
+    pub replay: Option<Recording>, // recorded foreign calls instead of the real ones
+    pub cache: Option<Cache>,      // for the results of `#[pure]` functions
+    pub limits: Vec<(String, Limits)>, // override the `#[limit]`s of those modules and functions
 }
 
 #[derive(Debug, Clone)]
@@ -18,7 +49,62 @@ pub enum InterpreterError {
     TypeMismatch(String),
     ArityMismatch(String),
     FFIError(String),
+    ForeignException(ForeignException, Vec<String>), // galois frames, innermost first
     NotReachable(String),
+    AffineViolation(Vec<String>), // every violation found by `--affine`
+    MissingField(String),
+}
+
+impl InterpreterError {
+    // foreign exceptions match their class and its bases, everything else its own variant name
+    pub fn is_a(&self, kind: &str) -> bool {
+        match self {
+            InterpreterError::UndefinedVariable(_) => kind == "UndefinedVariable",
+            InterpreterError::TypeMismatch(_) => kind == "TypeMismatch",
+            InterpreterError::ArityMismatch(_) => kind == "ArityMismatch",
+            InterpreterError::FFIError(_) => kind == "FFIError",
+            InterpreterError::ForeignException(exception, _) => exception.is_a(kind),
+            InterpreterError::NotReachable(_) => kind == "NotReachable",
+            InterpreterError::AffineViolation(_) => kind == "AffineViolation",
+            InterpreterError::MissingField(_) => kind == "MissingField",
+        }
+    }
+
+    // short description handed to `catch ... as e`
+    pub fn summary(&self) -> String {
+        match self {
+            InterpreterError::ForeignException(exception, _) => exception.to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    fn with_frame(self, frame: String) -> Self {
+        match self {
+            InterpreterError::ForeignException(exception, mut frames) => {
+                frames.push(frame);
+                InterpreterError::ForeignException(exception, frames)
+            }
+            error => error,
+        }
+    }
+}
+
+impl From<FFIError> for InterpreterError {
+    fn from(error: FFIError) -> Self {
+        match error {
+            FFIError::Exception(exception) => {
+                InterpreterError::ForeignException(exception, Vec::new())
+            }
+            FFIError::TypeMismatch(msg) => InterpreterError::TypeMismatch(msg),
+            ffi_error => InterpreterError::FFIError(ffi_error.to_string()),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for InterpreterError {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        InterpreterError::from(FFIError::from(error))
+    }
 }
 
 impl std::fmt::Display for InterpreterError {
@@ -28,53 +114,140 @@ impl std::fmt::Display for InterpreterError {
             InterpreterError::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
             InterpreterError::ArityMismatch(msg) => write!(f, "Arity mismatch: {}", msg),
             InterpreterError::FFIError(msg) => write!(f, "FFI error: {}", msg),
+            InterpreterError::ForeignException(exception, frames) => {
+                write!(f, "Foreign exception: {}", exception)?;
+                for frame in frames {
+                    write!(f, "\n  {}", frame)?;
+                }
+                if let Some(traceback) = &exception.traceback {
+                    write!(f, "\n{}", traceback.trim_end())?;
+                }
+                Ok(())
+            }
             InterpreterError::NotReachable(msg) => write!(f, "Not reachable: {}", msg),
+            InterpreterError::AffineViolation(violations) => {
+                write!(f, "Affine violation:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            InterpreterError::MissingField(msg) => write!(f, "Missing field: {}", msg),
         }
     }
 }
 
 impl Interpreter {
-    pub fn new(debug_mode: bool) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        debug_mode: bool,
+        modules: ModuleLoader,
+        options: Options,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut ffi = FFIBackend::new(modules.search_path().clone());
+        if options.parallel {
+            ffi.start_workers()?;
+        }
+        if let Some(seed) = options.schedule_seed {
+            ffi.shuffle_schedule(seed);
+        }
+        if let Some(fixtures) = options.mock {
+            ffi.use_mock(fixtures);
+        }
+        if let Some(recording) = options.replay {
+            ffi.replay_from(recording);
+        }
+        if let Some(cache) = options.cache {
+            ffi.use_cache(cache);
+        }
+        if let Some(recorder) = options.record {
+            ffi.record_to(recorder);
+        }
+        for (target, limits) in options.limits {
+            ffi.configure_limits(&target, limits)?;
+        }
         Ok(Interpreter {
             env: Rc::new(RefCell::new(Environment::new())),
             debug: DebugPrinter::new(debug_mode),
-            ffi: FFIBackend::new(),
+            ffi,
+            modules,
+            module_envs: HashMap::new(),
+            imports: HashMap::new(),
+            signatures: HashMap::new(),
+            parallel: options.parallel,
+            affine: options.affine,
+            outstanding: Vec::new(),
         })
     }
 
     pub fn interpret(&mut self, exprs: Vec<Rc<Expr>>) -> Result<Value, InterpreterError> {
-        let mut result = Value::Primitive(Primitive::Bool(false));
+        if self.affine {
+            self.declare(&exprs)?;
+            self.check_affine(&exprs)?;
+        }
 
+        let mut result = Value::Primitive(Primitive::None);
         for expr in exprs {
             result = self.eval_expr(&expr)?;
         }
 
+        // a script is only done once every call it started is
+        let result = force(result)?;
+        self.settle(0)?;
+        self.ffi.check_expectations()?;
         Ok(result)
     }
 
+    // waits for the calls started since `since`, failing with the first error among them
+    fn settle(&mut self, since: usize) -> Result<(), InterpreterError> {
+        for promise in self
+            .outstanding
+            .split_off(since.min(self.outstanding.len()))
+        {
+            futures::executor::block_on(promise)?;
+        }
+        Ok(())
+    }
+
     fn eval_expr(&mut self, expr: &Expr) -> Result<Value, InterpreterError> {
         match expr {
             Expr::Primitive(p) => Ok(Value::Primitive(p.clone())),
-            Expr::Variable(name) => self
-                .env
-                .borrow()
-                .get(name)
-                .cloned()
-                .ok_or_else(|| InterpreterError::UndefinedVariable(name.clone())),
-            Expr::FunctionDef(name, params, body) => {
+            Expr::Array(elements) => {
+                // every element is started before any is waited for, so calls in an array
+                // overlap (and batch) under `--parallel`
+                let values = elements
+                    .iter()
+                    .map(|element| self.eval_expr(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut array = Vec::with_capacity(values.len());
+                for value in values {
+                    match force(value)? {
+                        Value::Primitive(p) => array.push(p),
+                        other => {
+                            return Err(InterpreterError::TypeMismatch(format!(
+                                "Arrays can only hold primitive values, got {}",
+                                other
+                            )));
+                        }
+                    }
+                }
+                Ok(Value::Primitive(Primitive::Array(array)))
+            }
+            Expr::Variable(name) => self.lookup(name),
+            Expr::FunctionDef(name, params, body, annotations) => {
                 let func_value = Value::Function(
                     name.clone(),
                     params.clone(),
                     body.to_vec(),
                     Rc::clone(&self.env),
+                    annotations.clone(),
                 );
                 self.env
                     .borrow_mut()
                     .insert(name.clone(), func_value.clone());
                 Ok(func_value)
             }
-            Expr::FunctionCall(func, args) => {
-                let func_value = self.eval_expr(func)?;
+            Expr::FunctionCall(func, args, kwargs) => {
+                let func_value = force(self.eval_expr(func)?)?;
                 let original_env = Rc::clone(&self.env);
                 let arg_values: Result<Vec<Value>, InterpreterError> = args
                     .iter()
@@ -83,8 +256,21 @@ impl Interpreter {
                         self.eval_expr(arg)
                     })
                     .collect();
+                let kwarg_values: Result<Vec<(String, Value)>, InterpreterError> = kwargs
+                    .iter()
+                    .map(|(name, arg)| {
+                        self.env = Rc::clone(&original_env);
+                        Ok((name.clone(), self.eval_expr(arg)?))
+                    })
+                    .collect();
                 self.env = original_env;
-                self.apply_function(func_value, arg_values?)
+                self.apply_function(func_value, arg_values?, kwarg_values?)
+                    .map_err(|e| match &e {
+                        InterpreterError::ForeignException(_, frames) if frames.is_empty() => {
+                            e.with_frame(format!("at {}", expr))
+                        }
+                        _ => e,
+                    })
             }
             Expr::Return(e) => self.eval_expr(e),
             Expr::Assignment(name, expr) => {
@@ -92,18 +278,81 @@ impl Interpreter {
                 self.env.borrow_mut().insert(name.clone(), value.clone());
                 Ok(value)
             }
-            Expr::FFIDecl(module, name, alias) => {
-                let _ = self
-                    .ffi
-                    .load_module(module)
-                    .map_err(|e| InterpreterError::FFIError(e.to_string()))?;
+            Expr::FFIDecl(module, name, _, _, attributes) if name == "*" => {
+                let exports = self.import_module(module)?;
+                self.apply_attributes(module, attributes)?;
+                let mut env = self.env.borrow_mut();
+                for function in exports {
+                    let qualified = format!("{}.{}", module, function);
+                    match env.get_mut(&function) {
+                        None => {
+                            env.insert(function, Value::Glob(vec![qualified]));
+                        }
+                        Some(Value::Glob(providers)) => {
+                            if !providers.contains(&qualified) {
+                                providers.push(qualified);
+                            }
+                        }
+                        // local definitions and explicit imports shadow glob imports
+                        Some(_) => {}
+                    }
+                }
+                Ok(Value::Primitive(Primitive::None))
+            }
+            Expr::FFIDecl(module, name, alias, signature, attributes) => {
+                let exports = self.import_module(module)?;
+                let qualified = if is_galois_module(module) {
+                    let qualified = format!("{}.{}", module, name);
+                    if !exports.contains(name) {
+                        return Err(InterpreterError::FFIError(
+                            FFIError::FunctionNotFound(qualified).to_string(),
+                        ));
+                    }
+                    qualified
+                } else {
+                    self.ffi
+                        .declare(&format!("{}.{}", module, name), signature.as_ref())
+                        .map_err(|e| InterpreterError::FFIError(e.to_string()))?;
+                    let qualified = self
+                        .ffi
+                        .qualify(module, name)
+                        .map_err(|e| InterpreterError::FFIError(e.to_string()))?;
+                    if let Some(signature) = signature {
+                        self.signatures.insert(qualified.clone(), signature.clone());
+                    }
+                    qualified
+                };
+                self.apply_attributes(&qualified, attributes)?;
 
-                let ffi_name = alias.as_ref().unwrap_or(name);
+                let import_name = alias.as_ref().unwrap_or(name);
+                if let Some(existing) = self.imports.get(import_name)
+                    && *existing != qualified
+                {
+                    return Err(InterpreterError::FFIError(
+                        FFIError::AmbiguousFunction(
+                            import_name.to_string(),
+                            vec![existing.clone(), qualified],
+                        )
+                        .to_string(),
+                    ));
+                }
                 // implicit aliasing
+                let value = self.resolve_qualified(&qualified)?;
+                self.imports.insert(import_name.to_string(), qualified);
+                self.env.borrow_mut().insert(import_name.to_string(), value);
+                Ok(Value::Primitive(Primitive::None))
+            }
+            Expr::ModuleDecl(module, alias) => {
+                self.import_module(module)?;
+
+                let module_name = match alias {
+                    Some(alias) => alias.as_str(),
+                    None => module.rsplit('.').next().unwrap_or(module),
+                };
                 self.env
                     .borrow_mut()
-                    .insert(ffi_name.to_string(), Value::Ffi(name.to_string()));
-                Ok(Value::Primitive(Primitive::Bool(true)))
+                    .insert(module_name.to_string(), Value::Module(module.clone()));
+                Ok(Value::Primitive(Primitive::None))
             }
             Expr::InfixOp(_, _, _) => Err(InterpreterError::NotReachable(
                 "Infix operations should be handled by the parser".to_string(),
@@ -111,37 +360,270 @@ impl Interpreter {
             Expr::NotationDecl(_, _) => Err(InterpreterError::NotReachable(
                 "Notation declarations should be handled by the parser".to_string(),
             )),
+            Expr::Try(body, clauses) => {
+                // calls started in the body must finish inside it for their errors to be caught
+                let started = self.outstanding.len();
+                let result = self
+                    .eval_body(body)
+                    .and_then(force)
+                    .and_then(|value| self.settle(started).map(|_| value));
+                let error = match result {
+                    Ok(value) => return Ok(value),
+                    Err(error) => {
+                        self.outstanding.truncate(started);
+                        error
+                    }
+                };
+                let Some(clause) = clauses.iter().find(|clause| {
+                    clause.kinds.is_empty() || clause.kinds.iter().any(|kind| error.is_a(kind))
+                }) else {
+                    return Err(error);
+                };
+                if let Some(binding) = &clause.binding {
+                    self.env.borrow_mut().insert(
+                        binding.clone(),
+                        Value::Primitive(Primitive::String(error.summary())),
+                    );
+                }
+                self.eval_body(&clause.body)
+            }
+            Expr::Record(base, fields) => {
+                let mut record = match base {
+                    Some(base) => match force(self.eval_expr(base)?)? {
+                        Value::Primitive(Primitive::Record(fields)) => fields,
+                        other => {
+                            return Err(InterpreterError::TypeMismatch(format!(
+                                "Only records can be updated, got {}",
+                                other
+                            )));
+                        }
+                    },
+                    None => BTreeMap::new(),
+                };
+                // like array elements, every field is started before any is waited for
+                let values = fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), self.eval_expr(value)?)))
+                    .collect::<Result<Vec<_>, InterpreterError>>()?;
+                for (name, value) in values {
+                    match force(value)? {
+                        Value::Primitive(p) => {
+                            record.insert(name, p);
+                        }
+                        other => {
+                            return Err(InterpreterError::TypeMismatch(format!(
+                                "Records can only hold primitive values, got {}",
+                                other
+                            )));
+                        }
+                    }
+                }
+                Ok(Value::Primitive(Primitive::Record(record)))
+            }
+            Expr::Field(record, name) => match self.eval_expr(record)? {
+                Value::Module(module) => self.resolve_qualified(&format!("{}.{}", module, name)),
+                value => field(value, name),
+            },
         }
     }
 
-    fn apply_function(&mut self, func: Value, args: Vec<Value>) -> Result<Value, InterpreterError> {
-        match func {
-            Value::Function(name, params, body, closure_env) => {
-                self.debug.log_entry(&name, &args);
-                if args.len() != params.len() {
-                    let error = Err(InterpreterError::ArityMismatch(format!(
-                        "Function '{}' expects {} arguments, but got {}",
-                        name,
-                        params.len(),
-                        args.len()
+    fn eval_body(&mut self, body: &[Rc<Expr>]) -> Result<Value, InterpreterError> {
+        body.iter()
+            .try_fold(Value::Primitive(Primitive::None), |_, expr| {
+                self.eval_expr(expr)
+            })
+    }
+
+    // local bindings shadow glob imports, and fully qualified galois names (left behind by imported
+    // notations) reach their module
+    pub(super) fn lookup(&mut self, name: &str) -> Result<Value, InterpreterError> {
+        let value = self.env.borrow().get(name).cloned();
+        if let Some(value) = value {
+            return self.resolve_glob(name, value);
+        }
+
+        if is_galois_module(name) && name.matches('.').count() > 1 {
+            return self.resolve_qualified(name);
+        }
+        if let Some(builtin) = Builtin::named(name) {
+            return Ok(Value::Builtin(builtin));
+        }
+
+        Err(InterpreterError::UndefinedVariable(name.to_string()))
+    }
+
+    fn resolve_glob(&mut self, name: &str, value: Value) -> Result<Value, InterpreterError> {
+        match value {
+            Value::Glob(providers) => match providers.as_slice() {
+                [qualified] => self.resolve_qualified(qualified),
+                _ => Err(InterpreterError::FFIError(
+                    FFIError::AmbiguousFunction(name.to_string(), providers).to_string(),
+                )),
+            },
+            value => Ok(value),
+        }
+    }
+
+    // `qualified` is <language>.<module>.<function>
+    fn resolve_qualified(&mut self, qualified: &str) -> Result<Value, InterpreterError> {
+        let Some((module, function)) = qualified.rsplit_once('.') else {
+            return Err(InterpreterError::UndefinedVariable(qualified.to_string()));
+        };
+        if !is_galois_module(module) {
+            return self
+                .ffi
+                .qualify(module, function)
+                .map(Value::Ffi)
+                .map_err(|e| InterpreterError::FFIError(e.to_string()));
+        }
+
+        let value = self.module_env(module)?.borrow().get(function).cloned();
+        match value {
+            Some(value) => self.resolve_glob(qualified, value),
+            None => Err(InterpreterError::UndefinedVariable(qualified.to_string())),
+        }
+    }
+
+    // `target` is a fully qualified function, or a module for `use *`
+    fn apply_attributes(
+        &mut self,
+        target: &str,
+        attributes: &[Attribute],
+    ) -> Result<(), InterpreterError> {
+        for attribute in attributes {
+            if is_galois_module(target) {
+                return Err(InterpreterError::FFIError(format!(
+                    "{} only applies to foreign functions, not {}",
+                    attribute, target
+                )));
+            }
+            match attribute.name.as_str() {
+                "pure" | "io" | "affine" | "linear"
+                    if !attribute.args.is_empty() || !attribute.kwargs.is_empty() =>
+                {
+                    return Err(InterpreterError::ArityMismatch(format!(
+                        "#[{}] takes no arguments",
+                        attribute.name
                     )));
-                    self.debug
-                        .log_exit(&name, &error.clone().map_err(|e| e.to_string()));
-                    return error;
                 }
+                "pure" => self.ffi.declare_effect(target, Effect::Pure),
+                "io" => self.ffi.declare_effect(target, Effect::Io),
+                "affine" => self.ffi.declare_ownership(target, Ownership::Affine),
+                "linear" => self.ffi.declare_ownership(target, Ownership::Linear),
+                // `#[effect("print", "network")]`
+                "effect" => {
+                    let channels = attribute
+                        .args
+                        .iter()
+                        .map(|arg| match arg {
+                            Primitive::String(channel) => Ok(channel.clone()),
+                            other => Err(InterpreterError::TypeMismatch(format!(
+                                "#[effect] names its channels with strings, got {}",
+                                other
+                            ))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if channels.is_empty() || !attribute.kwargs.is_empty() {
+                        return Err(InterpreterError::ArityMismatch(
+                            "#[effect] takes the names of its channels, e.g. #[effect(\"print\")]"
+                                .to_string(),
+                        ));
+                    }
+                    self.ffi.declare_effect(target, Effect::named(channels));
+                }
+                "limit" if attribute.args.is_empty() => {
+                    let limits = Limits::parse(target, &attribute.kwargs)?;
+                    self.ffi.limit(target, limits)?;
+                }
+                "limit" => {
+                    return Err(InterpreterError::ArityMismatch(
+                        "#[limit] takes named arguments only".to_string(),
+                    ));
+                }
+                _ => {
+                    return Err(InterpreterError::FFIError(format!(
+                        "Unknown attribute {} on {}",
+                        attribute, target
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // loads a module and returns what it exports; galois modules are evaluated on first import
+    fn import_module(&mut self, module_path: &str) -> Result<Vec<String>, InterpreterError> {
+        if !is_galois_module(module_path) {
+            return self
+                .ffi
+                .load_module(module_path)
+                .map_err(|e| InterpreterError::FFIError(e.to_string()));
+        }
+        self.module_env(module_path)?;
+        self.modules
+            .load(module_path)
+            .map(|module| module.exports.clone())
+            .map_err(InterpreterError::FFIError)
+    }
+
+    // every galois module is evaluated once, into an environment of its own
+    fn module_env(
+        &mut self,
+        module_path: &str,
+    ) -> Result<Rc<RefCell<Environment>>, InterpreterError> {
+        if let Some(env) = self.module_envs.get(module_path) {
+            return Ok(Rc::clone(env));
+        }
+        let module = self
+            .modules
+            .load(module_path)
+            .map_err(InterpreterError::FFIError)?;
+
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let saved_env = std::mem::replace(&mut self.env, Rc::clone(&env));
+        let saved_imports = std::mem::take(&mut self.imports);
+        let result = self.eval_body(&module.exprs);
+        self.env = saved_env;
+        self.imports = saved_imports;
+
+        result.map_err(|e| e.with_frame(format!("in module {}", module_path)))?;
+        self.module_envs
+            .insert(module_path.to_string(), Rc::clone(&env));
+        Ok(env)
+    }
+
+    fn apply_function(
+        &mut self,
+        func: Value,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value, InterpreterError> {
+        match func {
+            Value::Function(name, params, body, closure_env, annotations) => {
+                self.debug.log_entry(&name, &args, &kwargs);
+                let bound = bind_arguments(&name, &params, args, kwargs)
+                    .and_then(|bound| contract::arguments(&name, bound, &annotations.params));
+                let bound = match bound {
+                    Ok(bound) => bound,
+                    Err(e) => {
+                        let error = Err(e);
+                        self.debug
+                            .log_exit(&name, &error.clone().map_err(|e| e.to_string()));
+                        return error;
+                    }
+                };
 
                 let mut new_env = (*closure_env).borrow().clone();
-                for (param, arg) in params.iter().zip(args.iter()) {
-                    new_env.insert(param.clone(), arg.clone());
+                for (param, arg) in bound {
+                    new_env.insert(param, arg);
                 }
                 let saved_env = Rc::clone(&self.env);
                 self.env = Rc::new(RefCell::new(new_env));
 
-                let result = body
-                    .iter()
-                    .try_fold(Value::Primitive(Primitive::Bool(false)), |_, expr| {
-                        self.eval_expr(expr)
-                    });
+                let result = self
+                    .eval_body(&body)
+                    .map_err(|e| e.with_frame(format!("in function {}", name)))
+                    .and_then(|value| contract::result(&name, value, &annotations.ret));
 
                 self.env = saved_env;
                 self.debug
@@ -150,19 +632,21 @@ impl Interpreter {
                 Ok(result?)
             }
             Value::Ffi(ffi_name) => {
-                self.debug.log_entry(&ffi_name, &args);
-                let result = self
-                    .ffi
-                    .call_function(&ffi_name, args)
-                    .map_err(|e| InterpreterError::FFIError(e.to_string()));
+                self.debug.log_entry(&ffi_name, &args, &kwargs);
+                let result = if self.parallel {
+                    self.spawn_call(&ffi_name, args, kwargs)
+                } else {
+                    self.call_foreign(&ffi_name, args, kwargs)
+                };
                 self.debug
                     .log_exit(&ffi_name, &result.clone().map_err(|e| e.to_string()));
                 result
             }
+            Value::Builtin(builtin) => builtin::call(builtin, args),
             Value::PartialApplication(func, prev_args) => {
                 let mut all_args = prev_args;
                 all_args.extend(args);
-                self.apply_function((*func).clone(), all_args)
+                self.apply_function((*func).clone(), all_args, kwargs)
             }
             _ => Err(InterpreterError::TypeMismatch(
                 "Attempted to call a non-function value".to_string(),
@@ -171,11 +655,442 @@ impl Interpreter {
     }
 }
 
+impl Interpreter {
+    fn call_foreign(
+        &mut self,
+        ffi_name: &str,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value, InterpreterError> {
+        let args = args
+            .into_iter()
+            .map(foreign_argument)
+            .collect::<Result<Vec<_>, _>>()?;
+        let kwargs = kwargs
+            .into_iter()
+            .map(|(name, value)| Ok((name, foreign_argument(value)?)))
+            .collect::<Result<Vec<_>, InterpreterError>>()?;
+        let signature = self.signatures.get(ffi_name);
+        if let Some(signature) = signature {
+            contract::foreign_arguments(ffi_name, &args, signature)?;
+        }
+        let result = self.ffi.call_function(ffi_name, args, kwargs)?;
+        if let Some(signature) = signature {
+            contract::foreign_result(ffi_name, &result, signature)?;
+        }
+        Ok(Value::Primitive(result))
+    }
+
+    // pending arguments are handed over as they are: the call starts when they resolve
+    fn spawn_call(
+        &mut self,
+        ffi_name: &str,
+        args: Vec<Value>,
+        kwargs: Vec<(String, Value)>,
+    ) -> Result<Value, InterpreterError> {
+        let mut args = args
+            .into_iter()
+            .map(pending_argument)
+            .collect::<Result<Vec<_>, _>>()?;
+        let kwargs = kwargs
+            .into_iter()
+            .map(|(name, value)| Ok((name, pending_argument(value)?)))
+            .collect::<Result<Vec<_>, InterpreterError>>()?;
+        let signature = self.signatures.get(ffi_name);
+        if let Some(signature) = signature {
+            args = args
+                .into_iter()
+                .enumerate()
+                .map(|(i, arg)| match signature.params.get(i) {
+                    Some(ty) => contract::checked(arg, ffi_name, Some(i), ty),
+                    None => arg,
+                })
+                .collect();
+        }
+        let mut promise = self.ffi.spawn_call(ffi_name, args, kwargs);
+        if let Some(ret) = signature.and_then(|signature| signature.ret.as_ref()) {
+            promise = contract::checked(promise, ffi_name, None, ret);
+        }
+
+        // finished successful calls need no settling; failed ones are kept to be reported
+        self.outstanding
+            .retain(|promise| !matches!(promise.peek(), Some(Ok(_))));
+        self.outstanding.push(promise.clone());
+        Ok(Value::Pending(promise))
+    }
+}
+
+// what calling a galois function may do: every effect of every call reachable from its body
+#[derive(Debug, Clone)]
+pub struct Inferred {
+    pub effect: Effect,
+    pub higher_order: bool, // it calls one of its parameters
+}
+
+impl Inferred {
+    fn pure() -> Self {
+        Inferred {
+            effect: Effect::Pure,
+            higher_order: false,
+        }
+    }
+
+    fn add(&mut self, other: Inferred) {
+        self.effect = std::mem::replace(&mut self.effect, Effect::Pure).join(other.effect);
+        self.higher_order |= other.higher_order;
+    }
+
+    fn add_effect(&mut self, effect: Effect) {
+        self.effect = std::mem::replace(&mut self.effect, Effect::Pure).join(effect);
+    }
+}
+
+impl fmt::Display for Inferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.effect)?;
+        if self.higher_order {
+            write!(f, ", and whatever its function arguments do")?;
+        }
+        Ok(())
+    }
+}
+
+// names bound inside the function being analysed
+#[derive(Default)]
+struct Scope {
+    params: HashSet<String>,  // calling one does what the caller passed
+    locals: HashSet<String>,  // assigned or imported while it runs, so calling one may do anything
+    defined: HashSet<String>, // nested functions, whose bodies are part of this one
+}
+
+impl Scope {
+    // whether `m` in `m.f` is bound while the function runs, rather than a module it closes over
+    fn binds_head(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Field(record, _) => self.binds_head(record),
+            Expr::Variable(name) => {
+                self.params.contains(name)
+                    || self.locals.contains(name)
+                    || self.defined.contains(name)
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Interpreter {
+    // evaluates the declarations of a script, nothing else, and returns the names of the
+    // functions it defines or imports by name
+    pub(super) fn declare(&mut self, exprs: &[Rc<Expr>]) -> Result<Vec<String>, InterpreterError> {
+        let mut names: Vec<String> = Vec::new();
+        for expr in exprs {
+            let name = match expr.as_ref() {
+                Expr::FunctionDef(name, ..) => Some(name),
+                Expr::FFIDecl(_, name, alias, _, _) if name != "*" => {
+                    Some(alias.as_ref().unwrap_or(name))
+                }
+                Expr::FFIDecl(..) | Expr::ModuleDecl(..) => None,
+                _ => continue,
+            };
+            if let Some(name) = name
+                && !names.contains(name)
+            {
+                names.push(name.clone());
+            }
+            self.eval_expr(expr)?;
+        }
+        Ok(names)
+    }
+
+    // infers what each function a script defines or imports by name may do
+    pub fn effects(
+        &mut self,
+        exprs: Vec<Rc<Expr>>,
+    ) -> Result<Vec<(String, Inferred)>, InterpreterError> {
+        self.declare(&exprs)?
+            .into_iter()
+            .map(|name| {
+                let value = self.lookup(&name)?;
+                let inferred = self.infer_value(&value, &mut HashSet::new());
+                Ok((name, inferred))
+            })
+            .collect()
+    }
+
+    // `visited` holds the galois functions already followed, so recursion ends
+    fn infer_value(&mut self, value: &Value, visited: &mut HashSet<String>) -> Inferred {
+        match value {
+            Value::Ffi(function) => Inferred {
+                effect: self.ffi.effect_of(function),
+                higher_order: false,
+            },
+            Value::Glob(providers) => {
+                let mut inferred = Inferred::pure();
+                for function in providers {
+                    inferred.add_effect(self.ffi.effect_of(function));
+                }
+                inferred
+            }
+            Value::PartialApplication(func, args) => {
+                let mut inferred = self.infer_value(func, visited);
+                for arg in args {
+                    inferred.add(self.infer_value(arg, visited));
+                }
+                inferred
+            }
+            Value::Function(name, params, body, closure_env, ..) => {
+                if !visited.insert(format!("{:p}.{}", Rc::as_ptr(closure_env), name)) {
+                    return Inferred::pure();
+                }
+                let saved_env = std::mem::replace(&mut self.env, Rc::clone(closure_env));
+                let mut scope = Scope {
+                    params: params.iter().cloned().collect(),
+                    ..Scope::default()
+                };
+                let mut inferred = Inferred::pure();
+                for expr in body {
+                    self.infer_expr(expr, &mut scope, visited, &mut inferred);
+                }
+                self.env = saved_env;
+                inferred
+            }
+            Value::Primitive(_) | Value::Module(_) | Value::Pending(_) | Value::Builtin(_) => {
+                Inferred::pure()
+            }
+        }
+    }
+
+    // a function mentioned anywhere counts as called, since it may be passed on to be
+    fn infer_expr(
+        &mut self,
+        expr: &Expr,
+        scope: &mut Scope,
+        visited: &mut HashSet<String>,
+        inferred: &mut Inferred,
+    ) {
+        match expr {
+            Expr::Primitive(_) | Expr::InfixOp(..) | Expr::NotationDecl(..) => {}
+            Expr::Array(elements) => {
+                for element in elements {
+                    self.infer_expr(element, scope, visited, inferred);
+                }
+            }
+            Expr::Variable(name) => {
+                if !scope.params.contains(name)
+                    && !scope.locals.contains(name)
+                    && !scope.defined.contains(name)
+                    && let Ok(value) = self.lookup(name)
+                {
+                    inferred.add(self.infer_value(&value, visited));
+                }
+            }
+            Expr::FunctionCall(func, args, kwargs) => {
+                match func.as_ref() {
+                    Expr::Variable(name) if scope.params.contains(name) => {
+                        inferred.higher_order = true
+                    }
+                    Expr::Variable(name) if scope.defined.contains(name) => {}
+                    Expr::Variable(name) if scope.locals.contains(name) => {
+                        inferred.add_effect(Effect::Io)
+                    }
+                    Expr::Variable(name) => match self.lookup(name) {
+                        Ok(value) => inferred.add(self.infer_value(&value, visited)),
+                        Err(_) => inferred.add_effect(Effect::Io),
+                    },
+                    func => match self.member(func).filter(|_| !scope.binds_head(func)) {
+                        Some(value) => inferred.add(self.infer_value(&value, visited)),
+                        // calls whatever another expression returns
+                        None => {
+                            self.infer_expr(func, scope, visited, inferred);
+                            inferred.add_effect(Effect::Io);
+                        }
+                    },
+                }
+                for arg in args.iter().chain(kwargs.iter().map(|(_, arg)| arg)) {
+                    self.infer_expr(arg, scope, visited, inferred);
+                }
+            }
+            Expr::Return(expr) => self.infer_expr(expr, scope, visited, inferred),
+            Expr::Assignment(name, expr) => {
+                self.infer_expr(expr, scope, visited, inferred);
+                scope.locals.insert(name.clone());
+            }
+            Expr::FunctionDef(name, params, body, _) => {
+                scope.defined.insert(name.clone());
+                scope.params.extend(params.iter().cloned());
+                for expr in body {
+                    self.infer_expr(expr, scope, visited, inferred);
+                }
+            }
+            Expr::FFIDecl(_, name, alias, _, _) => {
+                scope.locals.insert(alias.as_ref().unwrap_or(name).clone());
+            }
+            Expr::ModuleDecl(module, alias) => {
+                let name = alias
+                    .as_ref()
+                    .unwrap_or(module)
+                    .rsplit('.')
+                    .next()
+                    .unwrap_or(module);
+                scope.locals.insert(name.to_string());
+            }
+            Expr::Try(body, clauses) => {
+                for expr in body {
+                    self.infer_expr(expr, scope, visited, inferred);
+                }
+                for clause in clauses {
+                    if let Some(binding) = &clause.binding {
+                        scope.locals.insert(binding.clone());
+                    }
+                    for expr in &clause.body {
+                        self.infer_expr(expr, scope, visited, inferred);
+                    }
+                }
+            }
+            Expr::Record(base, fields) => {
+                for expr in base.iter().chain(fields.iter().map(|(_, value)| value)) {
+                    self.infer_expr(expr, scope, visited, inferred);
+                }
+            }
+            Expr::Field(record, _) => match self.member(expr).filter(|_| !scope.binds_head(expr)) {
+                Some(value) => inferred.add(self.infer_value(&value, visited)),
+                None => self.infer_expr(record, scope, visited, inferred),
+            },
+        }
+    }
+
+    // `m.f` for a module `m` bound by `use`, found without running anything
+    pub(super) fn member(&mut self, expr: &Expr) -> Option<Value> {
+        let Expr::Field(module, name) = expr else {
+            return None;
+        };
+        let Expr::Variable(module) = module.as_ref() else {
+            return None;
+        };
+        match self.lookup(module).ok()? {
+            Value::Module(module) => self.resolve_qualified(&format!("{}.{}", module, name)).ok(),
+            _ => None,
+        }
+    }
+}
+
+// a field of a record, once the record is known
+fn field(record: Value, name: &str) -> Result<Value, InterpreterError> {
+    match force(record)? {
+        Value::Primitive(Primitive::Record(mut fields)) => match fields.remove(name) {
+            Some(value) => Ok(Value::Primitive(value)),
+            None if fields.is_empty() => Err(InterpreterError::MissingField(format!(
+                "no field '{}' in an empty record",
+                name
+            ))),
+            None => Err(InterpreterError::MissingField(format!(
+                "no field '{}' in a record with fields {}",
+                name,
+                fields.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))),
+        },
+        other => Err(InterpreterError::TypeMismatch(format!(
+            "Cannot get field '{}' of {}, which is not a record",
+            name, other
+        ))),
+    }
+}
+
+// blocks until a pending value is known
+pub(super) fn force(value: Value) -> Result<Value, InterpreterError> {
+    match value {
+        Value::Pending(promise) => Ok(Value::Primitive(futures::executor::block_on(promise)?)),
+        value => Ok(value),
+    }
+}
+
+fn foreign_argument(value: Value) -> Result<Primitive, InterpreterError> {
+    match force(value)? {
+        Value::Primitive(p) => Ok(p),
+        other => Err(InterpreterError::TypeMismatch(format!(
+            "Cannot pass {} to foreign code",
+            other
+        ))),
+    }
+}
+
+fn pending_argument(value: Value) -> Result<Promise, InterpreterError> {
+    match value {
+        Value::Pending(promise) => Ok(promise),
+        Value::Primitive(p) => Ok(resolved(p)),
+        other => Err(InterpreterError::TypeMismatch(format!(
+            "Cannot pass {} to foreign code",
+            other
+        ))),
+    }
+}
+
+// matches positional arguments to parameters in order, then named arguments by parameter name
+fn bind_arguments(
+    name: &str,
+    params: &[String],
+    args: Vec<Value>,
+    kwargs: Vec<(String, Value)>,
+) -> Result<Vec<(String, Value)>, InterpreterError> {
+    if args.len() > params.len() {
+        return Err(InterpreterError::ArityMismatch(format!(
+            "Function '{}' expects {} arguments, but got {}",
+            name,
+            params.len(),
+            args.len()
+        )));
+    }
+
+    let mut bound: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+    bound.resize(params.len(), None);
+
+    for (arg_name, value) in kwargs {
+        let index = params.iter().position(|p| *p == arg_name).ok_or_else(|| {
+            InterpreterError::ArityMismatch(format!(
+                "Function '{}' has no parameter named '{}'",
+                name, arg_name
+            ))
+        })?;
+        if bound[index].is_some() {
+            return Err(InterpreterError::ArityMismatch(format!(
+                "Function '{}' got multiple values for parameter '{}'",
+                name, arg_name
+            )));
+        }
+        bound[index] = Some(value);
+    }
+
+    params
+        .iter()
+        .zip(bound)
+        .map(|(param, value)| {
+            value.map(|value| (param.clone(), value)).ok_or_else(|| {
+                InterpreterError::ArityMismatch(format!(
+                    "Function '{}' is missing an argument for parameter '{}'",
+                    name, param
+                ))
+            })
+        })
+        .collect()
+}
+
 pub fn interpret(
     exprs: Vec<Rc<Expr>>,
     debug: &mut DebugPrinter,
+    modules: ModuleLoader,
+    options: Options,
 ) -> Result<Value, InterpreterError> {
-    let mut interpreter = Interpreter::new(debug.debug_mode)
+    let mut interpreter = Interpreter::new(debug.debug_mode, modules, options)
         .map_err(|e| InterpreterError::FFIError(e.to_string()))?;
     interpreter.interpret(exprs)
 }
+
+pub fn effects(
+    exprs: Vec<Rc<Expr>>,
+    modules: ModuleLoader,
+    options: Options,
+) -> Result<Vec<(String, Inferred)>, InterpreterError> {
+    let mut interpreter = Interpreter::new(false, modules, options)
+        .map_err(|e| InterpreterError::FFIError(e.to_string()))?;
+    interpreter.effects(exprs)
+}
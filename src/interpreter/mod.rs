@@ -1,5 +1,11 @@
 // interpreter/mod.rs
 
+mod affine;
+mod builtin;
+mod check;
+mod contract;
 mod evaluator;
+pub mod modules;
 
-pub use self::evaluator::interpret;
+pub use self::check::Checker;
+pub use self::evaluator::{effects, interpret, Options};
@@ -0,0 +1,256 @@
+// interpreter/modules.rs
+
+use crate::ffi::search_path::SearchPath;
+use crate::parser::{apply_notations, parse_unexpanded};
+use crate::syntax::{CatchClause, Expr};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::rc::Rc;
+
+pub const GALOIS_LANGUAGE: &str = "galois";
+
+pub fn is_galois_module(module_path: &str) -> bool {
+    module_path
+        .split_once('.')
+        .is_some_and(|(language, _)| language == GALOIS_LANGUAGE)
+}
+
+pub struct Module {
+    pub path: String,
+    pub exprs: Vec<Rc<Expr>>,
+    pub exports: Vec<String>,
+    pub notations: Vec<Rc<Expr>>,
+}
+
+// parses `.gal` modules once, in import order, so their notations are known before the
+// importer is expanded. evaluation happens later, in the interpreter.
+pub struct ModuleLoader {
+    search_path: SearchPath,
+    modules: HashMap<String, Rc<Module>>,
+    loading: Vec<String>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_path: SearchPath) -> Self {
+        ModuleLoader {
+            search_path,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    pub fn search_path(&self) -> &SearchPath {
+        &self.search_path
+    }
+
+    pub fn parse_program(&mut self, input: &str) -> Result<Vec<Rc<Expr>>, String> {
+        let exprs = parse_unexpanded(input)?;
+        self.expand(exprs)
+    }
+
+    pub fn load(&mut self, module_path: &str) -> Result<Rc<Module>, String> {
+        if let Some(module) = self.modules.get(module_path) {
+            return Ok(Rc::clone(module));
+        }
+        if let Some(start) = self.loading.iter().position(|m| m == module_path) {
+            let mut cycle = self.loading[start..].to_vec();
+            cycle.push(module_path.to_string());
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+
+        let (_, name) = module_path
+            .split_once('.')
+            .ok_or_else(|| format!("Not a galois module: {}", module_path))?;
+        let file = self
+            .search_path
+            .resolve_source(name)
+            .map_err(|e| e.to_string())?;
+        let source = fs::read_to_string(&file)
+            .map_err(|e| format!("Cannot read {}: {}", file.display(), e))?;
+        let exprs = parse_unexpanded(&source)
+            .map_err(|e| format!("In module {} ({}):\n{}", module_path, file.display(), e))?;
+
+        let exports = top_level_names(&exprs)
+            .into_iter()
+            .filter(|name| !name.starts_with('_'))
+            .collect();
+        let notations = hygienic_notations(module_path, &exprs);
+
+        self.loading.push(module_path.to_string());
+        let expanded = self.expand(exprs);
+        self.loading.pop();
+
+        let module = Rc::new(Module {
+            path: module_path.to_string(),
+            exprs: expanded?,
+            exports,
+            notations,
+        });
+        self.modules
+            .insert(module_path.to_string(), Rc::clone(&module));
+        Ok(module)
+    }
+
+    // loads every imported galois module, then expands with their notations in front of ours
+    fn expand(&mut self, exprs: Vec<Rc<Expr>>) -> Result<Vec<Rc<Expr>>, String> {
+        let mut imported = Vec::new();
+        let mut seen = HashSet::new();
+        for expr in &exprs {
+            if let Expr::FFIDecl(module, ..) | Expr::ModuleDecl(module, _) = &**expr
+                && is_galois_module(module)
+                && seen.insert(module.clone())
+            {
+                imported.extend(self.load(module)?.notations.iter().cloned());
+            }
+        }
+        apply_notations(imported.into_iter().chain(exprs).collect())
+    }
+}
+
+fn top_level_names(exprs: &[Rc<Expr>]) -> Vec<String> {
+    exprs
+        .iter()
+        .filter_map(|expr| match &**expr {
+            Expr::FunctionDef(name, ..) | Expr::Assignment(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// an imported notation must keep meaning what it meant in its module: names the module
+// defines or imports from other galois modules are rewritten to fully qualified ones
+fn hygienic_notations(module_path: &str, exprs: &[Rc<Expr>]) -> Vec<Rc<Expr>> {
+    let mut origins: HashMap<String, String> = top_level_names(exprs)
+        .into_iter()
+        .map(|name| (name.clone(), format!("{}.{}", module_path, name)))
+        .collect();
+    for expr in exprs {
+        if let Expr::FFIDecl(module, name, alias, ..) = &**expr
+            && is_galois_module(module)
+            && name != "*"
+        {
+            origins.insert(
+                alias.clone().unwrap_or_else(|| name.clone()),
+                format!("{}.{}", module, name),
+            );
+        }
+    }
+
+    exprs
+        .iter()
+        .filter_map(|expr| match &**expr {
+            Expr::NotationDecl(pattern, expansion) => {
+                let bound: HashSet<&str> = pattern
+                    .variables
+                    .iter()
+                    .map(String::as_str)
+                    .chain(
+                        pattern
+                            .pattern
+                            .split_whitespace()
+                            .filter_map(|token| token.strip_prefix('$')),
+                    )
+                    .collect();
+                Some(Rc::new(Expr::NotationDecl(
+                    pattern.clone(),
+                    qualify(expansion, &origins, &bound),
+                )))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// `bound` holds the names the expansion binds itself: the notation's variables, and the
+// parameters, assignments and catch bindings around `expr`
+fn qualify<'a>(
+    expr: &'a Rc<Expr>,
+    origins: &HashMap<String, String>,
+    bound: &HashSet<&'a str>,
+) -> Rc<Expr> {
+    match &**expr {
+        Expr::Variable(name) if !bound.contains(name.as_str()) => match origins.get(name) {
+            Some(qualified) => Rc::new(Expr::Variable(qualified.clone())),
+            None => Rc::clone(expr),
+        },
+        Expr::FunctionCall(func, args, kwargs) => Rc::new(Expr::FunctionCall(
+            qualify(func, origins, bound),
+            args.iter()
+                .map(|arg| qualify(arg, origins, bound))
+                .collect(),
+            kwargs
+                .iter()
+                .map(|(name, arg)| (name.clone(), qualify(arg, origins, bound)))
+                .collect(),
+        )),
+        Expr::InfixOp(left, op, right) => Rc::new(Expr::InfixOp(
+            qualify(left, origins, bound),
+            op.clone(),
+            qualify(right, origins, bound),
+        )),
+        Expr::Array(elements) => Rc::new(Expr::Array(
+            elements
+                .iter()
+                .map(|element| qualify(element, origins, bound))
+                .collect(),
+        )),
+        Expr::Record(base, fields) => Rc::new(Expr::Record(
+            base.as_ref().map(|base| qualify(base, origins, bound)),
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), qualify(value, origins, bound)))
+                .collect(),
+        )),
+        Expr::Field(record, name) => {
+            Rc::new(Expr::Field(qualify(record, origins, bound), name.clone()))
+        }
+        Expr::Return(value) => Rc::new(Expr::Return(qualify(value, origins, bound))),
+        Expr::Assignment(name, value) => Rc::new(Expr::Assignment(
+            name.clone(),
+            qualify(value, origins, bound),
+        )),
+        Expr::FunctionDef(name, params, body, annotations) => {
+            let mut inner = bound.clone();
+            inner.insert(name.as_str());
+            inner.extend(params.iter().map(String::as_str));
+            Rc::new(Expr::FunctionDef(
+                name.clone(),
+                params.clone(),
+                qualify_body(body, origins, inner),
+                annotations.clone(),
+            ))
+        }
+        Expr::Try(body, clauses) => Rc::new(Expr::Try(
+            qualify_body(body, origins, bound.clone()),
+            clauses
+                .iter()
+                .map(|clause| {
+                    let mut inner = bound.clone();
+                    inner.extend(clause.binding.as_deref());
+                    CatchClause {
+                        body: qualify_body(&clause.body, origins, inner),
+                        ..clause.clone()
+                    }
+                })
+                .collect(),
+        )),
+        _ => Rc::clone(expr),
+    }
+}
+
+// a name assigned or defined in a body is bound from there on
+fn qualify_body<'a>(
+    body: &'a [Rc<Expr>],
+    origins: &HashMap<String, String>,
+    mut bound: HashSet<&'a str>,
+) -> Vec<Rc<Expr>> {
+    body.iter()
+        .map(|expr| {
+            let qualified = qualify(expr, origins, &bound);
+            if let Expr::Assignment(name, _) | Expr::FunctionDef(name, ..) = &**expr {
+                bound.insert(name.as_str());
+            }
+            qualified
+        })
+        .collect()
+}
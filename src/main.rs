@@ -7,41 +7,188 @@ mod parser;
 mod syntax;
 
 use debug::DebugPrinter;
-use interpreter::interpret;
-use parser::parse_program;
+use ffi::cache::Cache;
+use ffi::limit::load_limits;
+use ffi::mock::Fixtures;
+use ffi::record::{Recorder, Recording};
+use ffi::search_path::SearchPath;
+use interpreter::modules::ModuleLoader;
+use interpreter::{effects, interpret, Checker, Options};
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use syntax::Environment;
 
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} check <filename.gal> [--path <dir>]...\n       {} <filename.gal> [--debug] [--parallel] [--schedule-seed <n>] [--effects] [--affine] [--mock <fixtures.json>] [--record <log>] [--replay <log>] [--limits <limits.json>] [--no-cache] [--clear-cache] [--path <dir>]...",
+        program, program
+    );
+    std::process::exit(1);
+}
+
+fn or_exit<T>(result: Result<T, ffi::FFIError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// `galois check <filename.gal>`: reports type mismatches without running anything
+fn check(args: &[String]) -> ! {
+    let mut filename = None;
+    let mut paths = Vec::new();
+    let mut rest = args.iter().skip(2);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--path" => match rest.next() {
+                Some(dir) => paths.push(PathBuf::from(dir)),
+                None => usage(&args[0]),
+            },
+            _ if filename.is_none() => filename = Some(arg),
+            _ => usage(&args[0]),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| usage(&args[0]));
+
+    let content = fs::read_to_string(filename).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", filename, e);
+        std::process::exit(1);
+    });
+    let mut modules = ModuleLoader::new(SearchPath::from_env(paths, Some(Path::new(filename))));
+    let exprs = modules.parse_program(&content).unwrap_or_else(|e| {
+        eprintln!("Parse error: {}", e);
+        std::process::exit(1);
+    });
+
+    let mismatches = Checker::new(&mut modules).check(&exprs);
+    for mismatch in &mismatches {
+        eprintln!("Type mismatch {}", mismatch);
+    }
+    match mismatches.len() {
+        0 => std::process::exit(0),
+        1 => eprintln!("1 type mismatch"),
+        n => eprintln!("{} type mismatches", n),
+    }
+    std::process::exit(1)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename.gal> [--debug]", args[0]);
-        std::process::exit(1);
+        usage(&args[0]);
+    }
+    if args[1] == "check" {
+        check(&args);
     }
 
-    let filename = &args[1];
-    let debug_mode = args.contains(&"--debug".to_string());
+    let mut filename = None;
+    let mut debug_mode = false;
+    let mut options = Options::default();
+    let mut paths = Vec::new();
+    let mut use_cache = true;
+    let mut clear_cache = false;
+    let mut show_effects = false;
+
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--debug" => debug_mode = true,
+            "--parallel" => options.parallel = true,
+            // implies `--parallel`
+            "--schedule-seed" => match rest.next().and_then(|seed| seed.parse().ok()) {
+                Some(seed) => {
+                    options.parallel = true;
+                    options.schedule_seed = Some(seed);
+                }
+                None => usage(&args[0]),
+            },
+            "--effects" => show_effects = true,
+            "--affine" => options.affine = true,
+            "--mock" => match rest.next() {
+                Some(fixtures) => options.mock = Some(or_exit(Fixtures::load(Path::new(fixtures)))),
+                None => usage(&args[0]),
+            },
+            "--record" => match rest.next() {
+                Some(log) => options.record = Some(or_exit(Recorder::create(Path::new(log)))),
+                None => usage(&args[0]),
+            },
+            "--replay" => match rest.next() {
+                Some(log) => options.replay = Some(or_exit(Recording::load(Path::new(log)))),
+                None => usage(&args[0]),
+            },
+            "--limits" => match rest.next() {
+                Some(limits) => options.limits = or_exit(load_limits(Path::new(limits))),
+                None => usage(&args[0]),
+            },
+            "--no-cache" => use_cache = false,
+            "--clear-cache" => clear_cache = true,
+            "--path" => match rest.next() {
+                Some(dir) => paths.push(PathBuf::from(dir)),
+                None => usage(&args[0]),
+            },
+            _ if filename.is_none() => filename = Some(arg),
+            _ => usage(&args[0]),
+        }
+    }
+
+    let cache = Cache::new(Cache::default_dir());
+    if clear_cache {
+        if let Err(e) = cache.clear() {
+            eprintln!("Cannot clear the cache in {}: {}", cache.dir().display(), e);
+            std::process::exit(1);
+        }
+        // `--clear-cache` on its own only clears
+        if filename.is_none() {
+            return Ok(());
+        }
+    }
+    if use_cache {
+        options.cache = Some(cache);
+    }
+
+    let filename = filename.unwrap_or_else(|| usage(&args[0]));
+    if options.mock.is_some() && options.replay.is_some() {
+        eprintln!("--mock and --replay both replace the foreign backends; use one");
+        std::process::exit(1);
+    }
+    let search_path = SearchPath::from_env(paths, Some(Path::new(filename)));
 
     let content = fs::read_to_string(filename)?;
 
     let mut debug_printer = DebugPrinter::new(debug_mode);
 
     let parse_time = Instant::now();
-    let parsed = parse_program(&content);
+    let mut modules = ModuleLoader::new(search_path);
+    let parsed = modules.parse_program(&content);
     let parse_duration = parse_time.elapsed();
 
     let start_time = Instant::now();
 
     match parsed {
         Ok(exprs) => {
+            // `--effects` reports what the functions may do instead of running the script
+            if show_effects {
+                match effects(exprs, modules, options) {
+                    Ok(functions) => {
+                        for (name, inferred) in functions {
+                            println!("{}: {}", name, inferred);
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        eprintln!("Runtime error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
             if debug_mode {
                 for expr in &exprs {
                     debug_printer.log_expr(expr, &Environment::new(), 0);
                 }
             }
-            match interpret(exprs, &mut debug_printer) {
+            match interpret(exprs, &mut debug_printer, modules, options) {
                 Ok(result) => {
                     if debug_mode {
                         println!("Result:");
@@ -2,16 +2,22 @@
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until, take_while1},
-    character::complete::{alpha1, alphanumeric1, char, digit1, multispace1, one_of},
-    combinator::{all_consuming, map, opt, recognize, value},
-    error::{context, VerboseError},
-    multi::{many0, many1, separated_list0},
-    sequence::{delimited, pair, preceded, terminated, tuple},
+    bytes::complete::{tag, take_until, take_while1, take_while_m_n},
+    character::complete::{alpha1, alphanumeric1, anychar, char, multispace1, one_of, satisfy},
+    combinator::{
+        all_consuming, cut, map, map_opt, map_res, not, opt, recognize, success, value, verify,
+    },
+    error::{context, ErrorKind, ParseError, VerboseError},
+    multi::{many0, many0_count, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
-use crate::syntax::{Associativity, Expr, NotationPattern, Primitive};
+use crate::syntax::{
+    Annotations, Associativity, Attribute, Builtin, CatchClause, Expr, FormatSpec, NotationPattern,
+    Primitive, Signature, Type,
+};
+use num_bigint::BigInt;
 use std::rc::Rc;
 
 type ParseResult<'a, O> = IResult<&'a str, O, VerboseError<&'a str>>;
@@ -53,62 +59,310 @@ fn parse_primitive(input: &str) -> ParseResult<Rc<Expr>> {
         map(
             alt((
                 map(parse_float, Primitive::Float),
-                map(parse_int, Primitive::Int),
+                map(parse_int, Primitive::integer),
                 map(parse_string, Primitive::String),
                 map(parse_bool, Primitive::Bool),
-                map(parse_array, Primitive::Array),
+                value(Primitive::None, parse_none),
             )),
             |p| Rc::new(Expr::Primitive(p)),
         ),
     )(input)
 }
 
-fn parse_int(input: &str) -> ParseResult<i64> {
+// digits of a radix, with `_` allowed between them: `1_000_000`
+fn parse_digits<'a>(radix: u32) -> impl FnMut(&'a str) -> ParseResult<'a, &'a str> {
+    let digit = move |c: char| c.is_digit(radix);
+    recognize(pair(
+        satisfy(digit),
+        many0(preceded(opt(char('_')), satisfy(digit))),
+    ))
+}
+
+// a literal runs up to the next character that cannot be part of a name: `0b12` and `1e` are
+// errors, not `0b1` followed by `2`
+fn end_of_literal(input: &str) -> ParseResult<'_, ()> {
+    not(satisfy(|c: char| c.is_alphanumeric() || c == '_'))(input)
+}
+
+// of any length, in decimal or with a `0x`, `0o` or `0b` prefix, which must be followed by digits
+fn parse_int(input: &str) -> ParseResult<BigInt> {
+    let prefixed = |radix, lower, upper| {
+        pair(
+            value(radix, alt((tag(lower), tag(upper)))),
+            cut(terminated(parse_digits(radix), end_of_literal)),
+        )
+    };
     context(
         "integer",
-        map(recognize(pair(opt(char('-')), digit1)), |s: &str| {
-            s.parse().unwrap()
-        }),
+        map(
+            pair(
+                opt(char('-')),
+                alt((
+                    prefixed(16, "0x", "0X"),
+                    prefixed(8, "0o", "0O"),
+                    prefixed(2, "0b", "0B"),
+                    pair(success(10), terminated(parse_digits(10), end_of_literal)),
+                )),
+            ),
+            |(sign, (radix, digits))| {
+                let digits = digits.replace('_', "");
+                let n = BigInt::parse_bytes(digits.as_bytes(), radix)
+                    .expect("digits of the radix are an integer");
+                if sign.is_some() {
+                    -n
+                } else {
+                    n
+                }
+            },
+        ),
     )(input)
 }
 
+// `1.5`, `1.`, `.5` and `1e5`. a `.` followed by a name is a field, not a float
 fn parse_float(input: &str) -> ParseResult<f64> {
+    let exponent = || tuple((one_of("eE"), opt(one_of("+-")), parse_digits(10)));
     context(
         "float",
         map(
             recognize(tuple((
                 opt(char('-')),
-                digit1,
-                char('.'),
-                digit1,
-                opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+                alt((
+                    recognize(tuple((
+                        parse_digits(10),
+                        char('.'),
+                        parse_digits(10),
+                        opt(exponent()),
+                    ))),
+                    recognize(tuple((char('.'), parse_digits(10), opt(exponent())))),
+                    recognize(tuple((
+                        parse_digits(10),
+                        char('.'),
+                        not(satisfy(|c: char| {
+                            c.is_alphanumeric() || c == '_' || c == '.'
+                        })),
+                    ))),
+                    recognize(pair(parse_digits(10), exponent())),
+                )),
+                end_of_literal,
             ))),
-            |s: &str| s.parse().unwrap(),
+            |s: &str| {
+                s.replace('_', "")
+                    .parse()
+                    .expect("a float literal is a float")
+            },
         ),
     )(input)
 }
 
+// a `"..."` string, a `"""` block string or a raw one
 fn parse_string(input: &str) -> ParseResult<String> {
     context(
         "string",
+        alt((parse_raw_string, parse_block_string, parse_quoted_string)),
+    )(input)
+}
+
+// what follows a backslash in a string: `\n`, `\"`, `\u{1F600}`, ...
+fn parse_escape(input: &str) -> ParseResult<'_, char> {
+    preceded(
+        char('\\'),
+        alt((
+            value('"', char('"')),
+            value('\\', char('\\')),
+            value('\n', char('n')),
+            value('\r', char('r')),
+            value('\t', char('t')),
+            value('\0', char('0')),
+            map_opt(
+                delimited(
+                    tag("u{"),
+                    take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()),
+                    char('}'),
+                ),
+                |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+            ),
+        )),
+    )(input)
+}
+
+fn parse_quoted_string(input: &str) -> ParseResult<'_, String> {
+    delimited(
+        char('"'),
+        map(
+            many0(alt((
+                map(take_while1(|c| c != '"' && c != '\\'), String::from),
+                map(parse_escape, String::from),
+            ))),
+            |chunks| chunks.concat(),
+        ),
+        char('"'),
+    )(input)
+}
+
+// a multi-line string between `"""`s, dedented before its escapes are read
+fn parse_block_string(input: &str) -> ParseResult<'_, String> {
+    map_opt(
         delimited(
-            char('"'),
-            map(
+            tag("\"\"\""),
+            recognize(many0(alt((
+                take_while1(|c| c != '"' && c != '\\'),
+                recognize(pair(char('\\'), anychar)),
+                // a quote is text unless it starts the closing three, which are the last three of
+                // a run: `"""say "hi""""`
+                terminated(tag("\""), not(pair(tag("\"\""), not(char('"'))))),
+            )))),
+            tag("\"\"\""),
+        ),
+        |body| unescape(&dedent(body)),
+    )(input)
+}
+
+// no escapes: `r"C:\dir"`, with `#`s around the quotes when the text has quotes in it,
+// `r#"say "hi""#`, or a block `r"""..."""`
+fn parse_raw_string(input: &str) -> ParseResult<'_, String> {
+    let (input, hashes) = preceded(char('r'), recognize(many0_count(char('#'))))(input)?;
+    let block = format!("\"\"\"{}", hashes);
+    let close = format!("\"{}", hashes);
+    alt((
+        map(
+            delimited(
+                tag("\"\"\""),
+                take_until(block.as_str()),
+                tag(block.as_str()),
+            ),
+            dedent,
+        ),
+        map(
+            delimited(char('"'), take_until(close.as_str()), tag(close.as_str())),
+            String::from,
+        ),
+    ))(input)
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let chunks: ParseResult<Vec<String>> = all_consuming(many0(alt((
+        map(take_while1(|c| c != '\\'), String::from),
+        map(parse_escape, String::from),
+    ))))(text);
+    chunks.ok().map(|(_, chunks)| chunks.concat())
+}
+
+// the lines of a block string, less the line breaks right after the opening quotes and right
+// before the closing ones, and less the indentation its non-blank lines share
+fn dedent(body: &str) -> String {
+    let mut lines: Vec<&str> = body
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    if lines.len() > 1 && lines[0].trim().is_empty() {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines[lines.len() - 1].trim().is_empty() {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|a, b| {
+            let shared = a
+                .chars()
+                .zip(b.chars())
+                .take_while(|(x, y)| x == y)
+                .map(|(c, _)| c.len_utf8())
+                .sum();
+            &a[..shared]
+        })
+        .unwrap_or("");
+    lines
+        .iter()
+        .map(|line| line.strip_prefix(indent).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `f"Summarize {doc} in {n:.2f} words"`, with `{{` and `}}` for braces. it is desugared to
+// `$concat(["Summarize ", $format(doc, ""), " in ", $format(n, ".2f"), " words"])`
+fn parse_interpolated_string(input: &str) -> ParseResult<'_, Rc<Expr>> {
+    let builtin = |builtin: Builtin| Rc::new(Expr::Variable(builtin.name().to_string()));
+    context(
+        "interpolated string",
+        map(
+            delimited(
+                tag("f\""),
                 many0(alt((
-                    map(take_while1(|c| c != '"' && c != '\\'), String::from),
-                    map(tag("\\\""), |_| String::from("\"")),
-                    map(tag("\\\\"), |_| String::from("\\")),
-                    map(tag("\\n"), |_| String::from("\n")),
-                    map(tag("\\r"), |_| String::from("\r")),
-                    map(tag("\\t"), |_| String::from("\t")),
+                    map(
+                        many1(alt((
+                            map(
+                                take_while1(|c| !matches!(c, '"' | '\\' | '{' | '}')),
+                                String::from,
+                            ),
+                            map(parse_escape, String::from),
+                            value(String::from("{"), tag("{{")),
+                            value(String::from("}"), tag("}}")),
+                        ))),
+                        |chunks| Rc::new(Expr::Primitive(Primitive::String(chunks.concat()))),
+                    ),
+                    map(parse_interpolation, |(expr, spec)| {
+                        Rc::new(Expr::FunctionCall(
+                            builtin(Builtin::Format),
+                            vec![expr, Rc::new(Expr::Primitive(Primitive::String(spec)))],
+                            Vec::new(),
+                        ))
+                    }),
                 ))),
-                |chunks| chunks.concat(),
+                // anything else is a malformed hole, not the name `f` followed by a string
+                cut(char('"')),
             ),
-            char('"'),
+            move |parts| {
+                Rc::new(Expr::FunctionCall(
+                    builtin(Builtin::Concat),
+                    vec![Rc::new(Expr::Array(parts))],
+                    Vec::new(),
+                ))
+            },
         ),
     )(input)
 }
 
+// a `{...}` hole of an interpolated string: an expression, and a format spec after the first
+// `:` outside brackets and strings (so an operator with a `:` in it needs parentheses there)
+fn parse_interpolation(input: &str) -> ParseResult<'_, (Rc<Expr>, String)> {
+    let (body, _) = char('{')(input)?;
+    let mut depth = 0;
+    let mut colon = None;
+    let mut end = 0;
+    loop {
+        let rest = &body[end..];
+        match rest.chars().next() {
+            None => return Err(nom::Err::Error(VerboseError::from_char(rest, '}'))),
+            Some('"') => {
+                let (after, _) = parse_string(rest)?;
+                end = body.len() - after.len();
+                continue;
+            }
+            Some('(' | '[' | '{') => depth += 1,
+            Some('}') if depth == 0 => break,
+            Some(')' | ']' | '}') => depth -= 1,
+            Some(':') if depth == 0 && colon.is_none() => colon = Some(end),
+            Some(_) => {}
+        }
+        end += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    let (text, spec) = match colon {
+        Some(colon) => (&body[..colon], &body[colon + 1..end]),
+        None => (&body[..end], ""),
+    };
+    let (_, expr) = all_consuming(delimited(ws, parse_expr, ws))(text)?;
+    if FormatSpec::parse(spec).is_none() {
+        return Err(nom::Err::Error(VerboseError::from_error_kind(
+            spec,
+            ErrorKind::Verify,
+        )));
+    }
+    Ok((&body[end + 1..], (expr, spec.to_string())))
+}
+
 fn parse_bool(input: &str) -> ParseResult<bool> {
     context(
         "boolean",
@@ -116,6 +370,11 @@ fn parse_bool(input: &str) -> ParseResult<bool> {
     )(input)
 }
 
+// not the start of a name like `nonempty`
+fn parse_none(input: &str) -> ParseResult<'_, &str> {
+    terminated(tag("none"), end_of_literal)(input)
+}
+
 fn parse_array(input: &str) -> ParseResult<Vec<Rc<Expr>>> {
     context(
         "array",
@@ -127,22 +386,61 @@ fn parse_array(input: &str) -> ParseResult<Vec<Rc<Expr>>> {
     )(input)
 }
 
-fn parse_identifier(input: &str) -> ParseResult<&str> {
+// one part of a name: a field, or a segment of `python.module.function`
+fn parse_name(input: &str) -> ParseResult<'_, &str> {
     recognize(pair(
-        alt((alpha1, tag("_"), tag("."))),
-        many0(alt((alphanumeric1, tag("_"), tag(".")))),
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
     ))(input)
 }
 
+// a dotted path of a module, `python.os.path`, or of something in one
+fn parse_identifier(input: &str) -> ParseResult<&str> {
+    recognize(separated_list1(char('.'), parse_name))(input)
+}
+
 fn parse_variable(input: &str) -> ParseResult<Rc<Expr>> {
     context(
         "variable",
+        map(parse_name, |s: &str| Rc::new(Expr::Variable(s.to_string()))),
+    )(input)
+}
+
+// `m.f` calls `f` of a module bound by `use`, `r.f` a function kept in a record
+fn parse_path(input: &str) -> ParseResult<'_, Rc<Expr>> {
+    map(
+        pair(parse_variable, many0(preceded(char('.'), parse_name))),
+        |(variable, fields)| {
+            fields.into_iter().fold(variable, |record, name| {
+                Rc::new(Expr::Field(record, name.to_string()))
+            })
+        },
+    )(input)
+}
+
+fn parse_field(input: &str) -> ParseResult<'_, (String, Rc<Expr>)> {
+    separated_pair(
+        alt((map(parse_name, String::from), parse_string)),
+        delimited(ws, char(':'), ws),
+        parse_expr,
+    )(input)
+}
+
+// `{name: expr, "other name": expr}`, or `{base with name: expr}` for a copy of the record
+// `base` with those fields set
+fn parse_record(input: &str) -> ParseResult<'_, Rc<Expr>> {
+    context(
+        "record",
         map(
-            recognize(pair(
-                alt((alpha1, tag("_"), tag("."))),
-                many0(alt((alphanumeric1, tag("_"), tag(".")))),
-            )),
-            |s: &str| Rc::new(Expr::Variable(s.to_string())),
+            delimited(
+                pair(char('{'), ws),
+                pair(
+                    opt(terminated(parse_expr, pair(tag("with"), multispace1))),
+                    separated_list0(delimited(ws, char(','), ws), parse_field),
+                ),
+                pair(ws, char('}')),
+            ),
+            |(base, fields)| Rc::new(Expr::Record(base, fields)),
         ),
     )(input)
 }
@@ -171,28 +469,40 @@ fn parse_function_def(input: &str) -> ParseResult<Rc<Expr>> {
                 preceded(pair(opt(tag(FUNCTION_DECLARATION)), ws), parse_variable),
                 delimited(
                     char('('),
-                    separated_list0(delimited(ws, char(','), ws), parse_variable),
+                    separated_list0(
+                        delimited(ws, char(','), ws),
+                        pair(
+                            parse_variable,
+                            opt(preceded(delimited(ws, char(':'), ws), parse_type)),
+                        ),
+                    ),
                     char(')'),
                 ),
+                opt(preceded(delimited(ws, tag("->"), ws), parse_type)),
                 delimited(ws, char('{'), ws),
                 many0(terminated(parse_expr, delimited(ws, opt(char(';')), ws))),
                 delimited(ws, char('}'), ws),
             )),
-            |(name, params, _, body, _)| {
+            |(name, params, ret, _, body, _)| {
                 if let Expr::Variable(name) = &*name {
+                    let (params, types): (Vec<_>, Vec<_>) = params
+                        .into_iter()
+                        .map(|(e, ty)| {
+                            if let Expr::Variable(name) = &*e {
+                                (name.clone(), ty.unwrap_or_default())
+                            } else {
+                                panic!("Expected variable in function parameters")
+                            }
+                        })
+                        .unzip();
                     Rc::new(Expr::FunctionDef(
                         name.clone(),
-                        params
-                            .into_iter()
-                            .map(|e| {
-                                if let Expr::Variable(name) = &*e {
-                                    name.clone()
-                                } else {
-                                    panic!("Expected variable in function parameters")
-                                }
-                            })
-                            .collect(),
+                        params,
                         body,
+                        Annotations {
+                            params: types,
+                            ret: ret.unwrap_or_default(),
+                        },
                     ))
                 } else {
                     panic!("Expected variable name for function")
@@ -202,19 +512,57 @@ fn parse_function_def(input: &str) -> ParseResult<Rc<Expr>> {
     )(input)
 }
 
+fn parse_named_argument(input: &str) -> ParseResult<'_, (String, Rc<Expr>)> {
+    context(
+        "named argument",
+        map(
+            pair(
+                delimited(ws, parse_name, ws),
+                preceded(pair(char('='), not(char('='))), parse_expr),
+            ),
+            |(name, expr)| (name.to_string(), expr),
+        ),
+    )(input)
+}
+
+fn parse_argument(input: &str) -> ParseResult<'_, (Option<String>, Rc<Expr>)> {
+    alt((
+        map(parse_named_argument, |(name, expr)| (Some(name), expr)),
+        map(parse_expr, |expr| (None, expr)),
+    ))(input)
+}
+
 fn parse_function_call(input: &str) -> ParseResult<Rc<Expr>> {
     context(
         "function call",
         map(
             pair(
-                parse_variable,
+                parse_path,
                 delimited(
                     char('('),
-                    separated_list0(delimited(ws, char(','), ws), parse_expr),
+                    // positional arguments may not follow named ones
+                    verify(
+                        separated_list0(delimited(ws, char(','), ws), parse_argument),
+                        |args: &[(Option<String>, Rc<Expr>)]| {
+                            args.windows(2)
+                                .all(|pair| !(pair[0].0.is_some() && pair[1].0.is_none()))
+                        },
+                    ),
                     char(')'),
                 ),
             ),
-            |(func, args)| Rc::new(Expr::FunctionCall(func, args)),
+            |(func, args)| {
+                let (named, positional): (Vec<_>, Vec<_>) =
+                    args.into_iter().partition(|(name, _)| name.is_some());
+                Rc::new(Expr::FunctionCall(
+                    func,
+                    positional.into_iter().map(|(_, expr)| expr).collect(),
+                    named
+                        .into_iter()
+                        .filter_map(|(name, expr)| name.map(|name| (name, expr)))
+                        .collect(),
+                ))
+            },
         ),
     )(input)
 }
@@ -228,17 +576,76 @@ fn parse_return(input: &str) -> ParseResult<Rc<Expr>> {
     )(input)
 }
 
+fn parse_block(input: &str) -> ParseResult<'_, Vec<Rc<Expr>>> {
+    delimited(
+        pair(char('{'), ws),
+        many0(terminated(parse_expr, delimited(ws, opt(char(';')), ws))),
+        char('}'),
+    )(input)
+}
+
+fn parse_catch_clause(input: &str) -> ParseResult<'_, CatchClause> {
+    context(
+        "catch clause",
+        map(
+            tuple((
+                preceded(
+                    pair(tag("catch"), ws),
+                    separated_list0(
+                        delimited(ws, char(','), ws),
+                        verify(parse_identifier, |kind: &str| kind != "as"),
+                    ),
+                ),
+                opt(preceded(delimited(ws, tag("as"), ws), parse_name)),
+                preceded(ws, parse_block),
+            )),
+            |(kinds, binding, body)| CatchClause {
+                kinds: kinds.into_iter().map(String::from).collect(),
+                binding: binding.map(String::from),
+                body,
+            },
+        ),
+    )(input)
+}
+
+fn parse_try(input: &str) -> ParseResult<'_, Rc<Expr>> {
+    context(
+        "try",
+        map(
+            pair(
+                preceded(pair(tag("try"), ws), parse_block),
+                many1(preceded(ws, parse_catch_clause)),
+            ),
+            |(body, clauses)| Rc::new(Expr::Try(body, clauses)),
+        ),
+    )(input)
+}
+
 fn parse_term(input: &str) -> ParseResult<Rc<Expr>> {
     context(
         "term",
         delimited(
             ws,
-            alt((
-                parse_primitive,
-                parse_function_call,
-                parse_variable,
-                delimited(char('('), parse_expr, char(')')),
-            )),
+            map(
+                pair(
+                    alt((
+                        parse_interpolated_string,
+                        parse_primitive,
+                        map(parse_array, |elements| Rc::new(Expr::Array(elements))),
+                        parse_record,
+                        parse_function_call,
+                        parse_variable,
+                        delimited(char('('), parse_expr, char(')')),
+                    )),
+                    // fields of a record, or members of a module
+                    many0(preceded(char('.'), parse_name)),
+                ),
+                |(term, fields)| {
+                    fields.into_iter().fold(term, |record, name| {
+                        Rc::new(Expr::Field(record, name.to_string()))
+                    })
+                },
+            ),
             ws,
         ),
     )(input)
@@ -270,7 +677,10 @@ fn parse_notation_pattern(input: &str) -> ParseResult<NotationPattern> {
                     delimited(ws, tag("with"), ws),
                     separated_list0(delimited(ws, char(','), ws), parse_variable),
                 )),
-                opt(preceded(delimited(ws, tag("precedence"), ws), parse_int)),
+                opt(preceded(
+                    delimited(ws, tag("precedence"), ws),
+                    map_res(parse_int, |p| i32::try_from(&p)),
+                )),
                 opt(preceded(
                     delimited(ws, tag("associativity"), ws),
                     alt((
@@ -293,7 +703,7 @@ fn parse_notation_pattern(input: &str) -> ParseResult<NotationPattern> {
                         }
                     })
                     .collect(),
-                precedence: precedence.map(|p| p as i32),
+                precedence,
                 associativity: associativity.unwrap_or(Associativity::None),
             },
         ),
@@ -314,19 +724,181 @@ fn parse_notation_decl(input: &str) -> ParseResult<Rc<Expr>> {
     )(input)
 }
 
-fn parse_ffi_decl(input: &str) -> ParseResult<Rc<Expr>> {
+// `int`, `float`, `string`, `bool`, `none`, `any`, `record`, `array[T]`, or `(T, ...) -> T`
+// for functions
+fn parse_type(input: &str) -> ParseResult<'_, Type> {
+    context(
+        "type",
+        alt((
+            value(Type::Int, tag("int")),
+            value(Type::Long, tag("long")),
+            value(Type::I64, tag("i64")),
+            value(Type::SizeT, tag("size_t")),
+            value(Type::Float, tag("float")),
+            value(Type::String, tag("string")),
+            value(Type::Bool, tag("bool")),
+            value(Type::None, tag("none")),
+            value(Type::Any, tag("any")),
+            value(Type::Record, tag("record")),
+            map(
+                preceded(
+                    tag("array"),
+                    delimited(pair(char('['), ws), parse_type, pair(ws, char(']'))),
+                ),
+                |element| Type::Array(Box::new(element)),
+            ),
+            map(
+                separated_pair(
+                    delimited(
+                        pair(char('('), ws),
+                        separated_list0(delimited(ws, char(','), ws), parse_type),
+                        pair(ws, char(')')),
+                    ),
+                    delimited(ws, tag("->"), ws),
+                    parse_type,
+                ),
+                |(params, ret)| Type::Function(params, Box::new(ret)),
+            ),
+        )),
+    )(input)
+}
+
+fn parse_signature(input: &str) -> ParseResult<'_, Signature> {
+    context(
+        "signature",
+        map(
+            pair(
+                delimited(
+                    pair(char('('), ws),
+                    separated_list0(delimited(ws, char(','), ws), parse_type),
+                    pair(ws, char(')')),
+                ),
+                opt(preceded(delimited(ws, tag("->"), ws), parse_type)),
+            ),
+            |(params, ret)| Signature { params, ret },
+        ),
+    )(input)
+}
+
+// a constant: a primitive or an array of constants
+fn parse_literal(input: &str) -> ParseResult<'_, Primitive> {
+    alt((
+        map(parse_primitive, |expr| match &*expr {
+            Expr::Primitive(p) => p.clone(),
+            _ => unreachable!("parse_primitive only builds primitives"),
+        }),
+        map(
+            delimited(
+                pair(char('['), ws),
+                separated_list0(delimited(ws, char(','), ws), parse_literal),
+                pair(ws, char(']')),
+            ),
+            Primitive::Array,
+        ),
+    ))(input)
+}
+
+fn parse_attribute_argument(input: &str) -> ParseResult<'_, (Option<String>, Primitive)> {
+    alt((
+        map(
+            separated_pair(parse_name, delimited(ws, char('='), ws), parse_literal),
+            |(name, value)| (Some(name.to_string()), value),
+        ),
+        map(parse_literal, |value| (None, value)),
+    ))(input)
+}
+
+// `#[pure]`, `#[limit(concurrency = 4)]`
+fn parse_attribute(input: &str) -> ParseResult<'_, Attribute> {
+    context(
+        "attribute",
+        map(
+            delimited(
+                pair(tag("#["), ws),
+                pair(
+                    parse_identifier,
+                    opt(delimited(
+                        pair(ws, char('(')),
+                        separated_list0(
+                            delimited(ws, char(','), ws),
+                            preceded(ws, parse_attribute_argument),
+                        ),
+                        pair(ws, char(')')),
+                    )),
+                ),
+                pair(ws, char(']')),
+            ),
+            |(name, args)| {
+                let (named, positional): (Vec<_>, Vec<_>) = args
+                    .unwrap_or_default()
+                    .into_iter()
+                    .partition(|(name, _)| name.is_some());
+                Attribute {
+                    name: name.to_string(),
+                    args: positional.into_iter().map(|(_, value)| value).collect(),
+                    kwargs: named
+                        .into_iter()
+                        .filter_map(|(name, value)| name.map(|name| (name, value)))
+                        .collect(),
+                }
+            },
+        ),
+    )(input)
+}
+
+// `from m use a, b(int) -> int as c` declares one function per name; attributes in front
+// apply to each of them
+fn parse_ffi_decl(input: &str) -> ParseResult<Vec<Rc<Expr>>> {
     context(
         "ffi declaration",
         map(
             tuple((
+                many0(terminated(parse_attribute, ws)),
                 preceded(pair(tag("from"), ws), parse_identifier),
-                preceded(delimited(ws, tag("use"), ws), parse_identifier),
-                opt(preceded(delimited(ws, tag("as"), ws), parse_identifier)),
+                preceded(
+                    delimited(ws, tag("use"), ws),
+                    alt((
+                        map(tag("*"), |glob: &str| vec![(glob, None, None)]),
+                        separated_list1(
+                            delimited(ws, char(','), ws),
+                            tuple((
+                                parse_identifier,
+                                opt(preceded(ws, parse_signature)),
+                                opt(preceded(delimited(ws, tag("as"), ws), parse_identifier)),
+                            )),
+                        ),
+                    )),
+                ),
             )),
-            |(module, name, alias)| {
-                Rc::new(Expr::FFIDecl(
+            |(attributes, module, names)| {
+                names
+                    .into_iter()
+                    .map(|(name, signature, alias)| {
+                        Rc::new(Expr::FFIDecl(
+                            module.to_string(),
+                            name.to_string(),
+                            alias.map(|a| a.to_string()),
+                            signature,
+                            attributes.clone(),
+                        ))
+                    })
+                    .collect()
+            },
+        ),
+    )(input)
+}
+
+fn parse_module_decl(input: &str) -> ParseResult<'_, Rc<Expr>> {
+    context(
+        "module declaration",
+        map(
+            pair(
+                preceded(pair(tag("use"), multispace1), parse_identifier),
+                opt(preceded(delimited(ws, tag("as"), ws), parse_name)),
+            ),
+            |(module, alias)| {
+                Rc::new(Expr::ModuleDecl(
                     module.to_string(),
-                    name.to_string(),
                     alias.map(|a| a.to_string()),
                 ))
             },
@@ -340,6 +912,7 @@ fn parse_expr(input: &str) -> ParseResult<Rc<Expr>> {
         delimited(
             ws,
             alt((
+                parse_try,
                 parse_function_def,
                 parse_assignment,
                 parse_return,
@@ -350,20 +923,203 @@ fn parse_expr(input: &str) -> ParseResult<Rc<Expr>> {
     )(input)
 }
 
-fn parse_top_level_expr(input: &str) -> ParseResult<Rc<Expr>> {
+fn parse_top_level_expr(input: &str) -> ParseResult<Vec<Rc<Expr>>> {
     context(
         "top level expression",
-        alt((
-            parse_ffi_decl,
-            parse_notation_decl,
-            terminated(parse_expr, delimited(ws, opt(char(';')), ws)),
-        )),
+        terminated(
+            alt((
+                parse_ffi_decl,
+                map(parse_module_decl, |expr| vec![expr]),
+                map(parse_notation_decl, |expr| vec![expr]),
+                map(parse_expr, |expr| vec![expr]),
+            )),
+            delimited(ws, opt(char(';')), ws),
+        ),
     )(input)
 }
 
 pub fn parse_program(input: &str) -> ParseResult<Vec<Rc<Expr>>> {
     context(
         "program",
-        all_consuming(delimited(ws, many1(parse_top_level_expr), ws)),
+        map(
+            all_consuming(delimited(ws, many1(parse_top_level_expr), ws)),
+            |exprs| exprs.into_iter().flatten().collect(),
+        ),
     )(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the primitive a whole literal parses to, as its `Debug` form
+    fn literal(source: &str) -> Option<String> {
+        let (_, expr) = all_consuming(parse_primitive)(source).ok()?;
+        match &*expr {
+            Expr::Primitive(p) => Some(format!("{:?}", p)),
+            _ => None,
+        }
+    }
+
+    fn string(source: &str) -> Option<String> {
+        all_consuming(parse_string)(source)
+            .ok()
+            .map(|(_, text)| text)
+    }
+
+    #[test]
+    fn integers_in_each_radix() {
+        assert_eq!(literal("0xff").as_deref(), Some("Int(255)"));
+        assert_eq!(literal("0XFF").as_deref(), Some("Int(255)"));
+        assert_eq!(literal("0o17").as_deref(), Some("Int(15)"));
+        assert_eq!(literal("0b101").as_deref(), Some("Int(5)"));
+        assert_eq!(literal("-0x10").as_deref(), Some("Int(-16)"));
+        assert_eq!(
+            literal("0xffffffffffffffffff").as_deref(),
+            Some("BigInt(4722366482869645213695)")
+        );
+    }
+
+    #[test]
+    fn literals_end_where_a_name_could_not_go_on() {
+        for source in [
+            "0x", "0o", "0B", "0b12", "0o8", "0xfg", "12ab", "1e", "1.5x", "1_",
+        ] {
+            assert_eq!(literal(source), None, "{}", source);
+        }
+        assert!(parse_program("x = 0b12").is_err());
+        assert!(parse_program("x = 0x").is_err());
+    }
+
+    #[test]
+    fn underscores_between_digits() {
+        assert_eq!(literal("1_000_000").as_deref(), Some("Int(1000000)"));
+        assert_eq!(literal("0xff_ff").as_deref(), Some("Int(65535)"));
+        assert_eq!(literal("1_0.2_5").as_deref(), Some("Float(10.25)"));
+        assert_eq!(literal("1__0"), None);
+    }
+
+    #[test]
+    fn floats_with_a_missing_side() {
+        assert_eq!(literal(".5").as_deref(), Some("Float(0.5)"));
+        assert_eq!(literal("1.").as_deref(), Some("Float(1.0)"));
+        assert_eq!(literal("-2.5E-1").as_deref(), Some("Float(-0.25)"));
+        assert_eq!(literal("1e3").as_deref(), Some("Float(1000.0)"));
+        // a name after the dot is a field
+        let (_, expr) = all_consuming(parse_expr)("1.a").unwrap();
+        assert!(matches!(&*expr, Expr::Field(_, name) if name == "a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            string(r#""tab\t quote\" \u{1F600}""#).as_deref(),
+            Some("tab\t quote\" \u{1F600}")
+        );
+        assert_eq!(string(r#""\u{41}\\""#).as_deref(), Some("A\\"));
+        assert_eq!(string(r#""\u{110000}""#), None);
+        assert_eq!(string(r#""\u{}""#), None);
+        assert_eq!(string(r#""\q""#), None);
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(string(r#"r"C:\dir\n""#).as_deref(), Some(r"C:\dir\n"));
+        assert_eq!(
+            string(r###"r#"say "hi""#"###).as_deref(),
+            Some(r#"say "hi""#)
+        );
+        assert_eq!(
+            string("r\"\"\"\n    a\\n\n    \"\"\"").as_deref(),
+            Some("a\\n")
+        );
+    }
+
+    #[test]
+    fn block_strings_are_dedented() {
+        assert_eq!(
+            string("\"\"\"\n    a\n      b\n\n    c\n    \"\"\"").as_deref(),
+            Some("a\n  b\n\nc")
+        );
+        assert_eq!(
+            string("\"\"\"\r\n  a\\tb\r\n  \"\"\"").as_deref(),
+            Some("a\tb")
+        );
+        assert_eq!(string("\"\"\"one line\"\"\"").as_deref(), Some("one line"));
+    }
+
+    #[test]
+    fn block_strings_ending_in_quotes() {
+        assert_eq!(string(r#""""say "hi"""""#).as_deref(), Some(r#"say "hi""#));
+        assert_eq!(string(r#""""a""""""#).as_deref(), Some(r#"a"""#));
+        assert_eq!(string(r#"""""b""""#).as_deref(), Some(r#""b"#));
+    }
+
+    // the parts of an interpolated string: text as it is, holes as `expr|spec`
+    fn holes(source: &str) -> Option<Vec<String>> {
+        let (_, expr) = all_consuming(parse_interpolated_string)(source).ok()?;
+        let Expr::FunctionCall(_, args, _) = &*expr else {
+            return None;
+        };
+        let Expr::Array(parts) = &*args[0] else {
+            return None;
+        };
+        parts
+            .iter()
+            .map(|part| match &**part {
+                Expr::Primitive(Primitive::String(text)) => Some(text.clone()),
+                Expr::FunctionCall(_, args, _) => match &*args[1] {
+                    Expr::Primitive(Primitive::String(spec)) => {
+                        Some(format!("{}|{}", args[0], spec))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interpolated_braces() {
+        assert_eq!(
+            holes(r#"f"{{a}} is {a}""#),
+            Some(vec!["{a} is ".to_string(), "a|".to_string()])
+        );
+        assert_eq!(holes(r#"f"{{}}""#), Some(vec!["{}".to_string()]));
+        assert_eq!(holes(r#"f"{a""#), None);
+        assert_eq!(holes(r#"f"a}""#), None);
+    }
+
+    #[test]
+    fn interpolated_specs_start_outside_strings_and_brackets() {
+        assert_eq!(
+            holes(r#"f"{g("a:b"):>8}""#),
+            Some(vec![r#"g("a:b")|>8"#.to_string()])
+        );
+        assert_eq!(
+            holes(r#"f"{[1, {a: 2}].a}""#),
+            Some(vec!["[1, {a: 2}].a|".to_string()])
+        );
+        // `{{` would be a brace, so a record opening a hole is spaced off
+        assert_eq!(
+            holes(r#"f"{ {a: 0.5}.a:*^+09.1%}""#),
+            Some(vec!["{a: 0.5}.a|*^+09.1%".to_string()])
+        );
+        assert_eq!(
+            holes(r#"f"{x:.2f} and {y:e}""#),
+            Some(vec![
+                "x|.2f".to_string(),
+                " and ".to_string(),
+                "y|e".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn interpolated_specs_are_checked() {
+        assert_eq!(holes(r#"f"{x:q}""#), None);
+        assert_eq!(holes(r#"f"{x:>5000}""#), None);
+        assert_eq!(holes(r#"f"{x:.2f:}""#), None);
+        assert!(parse_program(r#"s = f"{x:%H:%M}""#).is_err());
+    }
+}
@@ -11,9 +11,10 @@ use std::rc::Rc;
 pub use self::base::parse_program as parse_base_program;
 pub use self::notation::apply_notations;
 
-pub fn parse_program(input: &str) -> Result<Vec<Rc<Expr>>, String> {
+// keeps notation declarations in place so imported ones can be added before expanding
+pub fn parse_unexpanded(input: &str) -> Result<Vec<Rc<Expr>>, String> {
     match parse_base_program(input).finish() {
-        Ok((_, exprs)) => apply_notations(exprs),
+        Ok((_, exprs)) => Ok(exprs),
         Err(e) => Err(convert_error(input, e)),
     }
 }
@@ -1,6 +1,6 @@
 // parser/notation.rs
 
-use crate::syntax::{Expr, NotationPattern};
+use crate::syntax::{CatchClause, Expr, NotationPattern};
 use std::collections::HashMap;
 use std::rc::Rc;
 
@@ -37,21 +37,36 @@ pub fn apply_notations(ast: Vec<Rc<Expr>>) -> Result<Vec<Rc<Expr>>, String> {
 
 fn expand_expr(expr: Rc<Expr>, notations: &[Notation]) -> Result<Rc<Expr>, String> {
     let expanded = match &*expr {
-        Expr::FunctionDef(name, params, body) => Rc::new(Expr::FunctionDef(
+        Expr::FunctionDef(name, params, body, annotations) => Rc::new(Expr::FunctionDef(
             name.clone(),
             params.clone(),
             body.iter()
                 .map(|e| expand_expr(Rc::clone(e), notations))
                 .collect::<Result<_, _>>()?,
+            annotations.clone(),
         )),
-        Expr::FunctionCall(func, args) => {
+        Expr::FunctionCall(func, args, kwargs) => {
             let expanded_func = expand_expr(Rc::clone(func), notations)?;
             let expanded_args = args
                 .iter()
                 .map(|arg| expand_expr(Rc::clone(arg), notations))
                 .collect::<Result<Vec<_>, _>>()?;
-            Rc::new(Expr::FunctionCall(expanded_func, expanded_args))
+            let expanded_kwargs = kwargs
+                .iter()
+                .map(|(name, arg)| Ok((name.clone(), expand_expr(Rc::clone(arg), notations)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Rc::new(Expr::FunctionCall(
+                expanded_func,
+                expanded_args,
+                expanded_kwargs,
+            ))
         }
+        Expr::Array(elements) => Rc::new(Expr::Array(
+            elements
+                .iter()
+                .map(|e| expand_expr(Rc::clone(e), notations))
+                .collect::<Result<_, _>>()?,
+        )),
         Expr::Return(e) => Rc::new(Expr::Return(expand_expr(Rc::clone(e), notations)?)),
         Expr::Assignment(name, e) => Rc::new(Expr::Assignment(
             name.clone(),
@@ -62,6 +77,38 @@ fn expand_expr(expr: Rc<Expr>, notations: &[Notation]) -> Result<Rc<Expr>, Strin
             let expanded_right = expand_expr(Rc::clone(right), notations)?;
             Rc::new(Expr::InfixOp(expanded_left, op.clone(), expanded_right))
         }
+        Expr::Try(body, clauses) => Rc::new(Expr::Try(
+            body.iter()
+                .map(|e| expand_expr(Rc::clone(e), notations))
+                .collect::<Result<_, _>>()?,
+            clauses
+                .iter()
+                .map(|clause| {
+                    Ok(CatchClause {
+                        kinds: clause.kinds.clone(),
+                        binding: clause.binding.clone(),
+                        body: clause
+                            .body
+                            .iter()
+                            .map(|e| expand_expr(Rc::clone(e), notations))
+                            .collect::<Result<_, _>>()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        )),
+        Expr::Record(base, fields) => Rc::new(Expr::Record(
+            base.as_ref()
+                .map(|base| expand_expr(Rc::clone(base), notations))
+                .transpose()?,
+            fields
+                .iter()
+                .map(|(name, e)| Ok((name.clone(), expand_expr(Rc::clone(e), notations)?)))
+                .collect::<Result<_, String>>()?,
+        )),
+        Expr::Field(record, name) => Rc::new(Expr::Field(
+            expand_expr(Rc::clone(record), notations)?,
+            name.clone(),
+        )),
         _ => Rc::clone(&expr),
     };
 
@@ -96,14 +143,28 @@ fn expand_notation(
             .get(name)
             .cloned()
             .unwrap_or_else(|| Rc::new(Expr::Variable(name.clone())))),
-        Expr::FunctionCall(func, args) => {
+        Expr::FunctionCall(func, args, kwargs) => {
             let expanded_func = expand_notation(func, bindings)?;
             let expanded_args = args
                 .iter()
                 .map(|arg| expand_notation(arg, bindings))
                 .collect::<Result<Vec<_>, _>>()?;
-            Ok(Rc::new(Expr::FunctionCall(expanded_func, expanded_args)))
+            let expanded_kwargs = kwargs
+                .iter()
+                .map(|(name, arg)| Ok((name.clone(), expand_notation(arg, bindings)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(Rc::new(Expr::FunctionCall(
+                expanded_func,
+                expanded_args,
+                expanded_kwargs,
+            )))
         }
+        Expr::Array(elements) => Ok(Rc::new(Expr::Array(
+            elements
+                .iter()
+                .map(|e| expand_notation(e, bindings))
+                .collect::<Result<_, _>>()?,
+        ))),
         Expr::InfixOp(left, op, right) => {
             let expanded_left = expand_notation(left, bindings)?;
             let expanded_right = expand_notation(right, bindings)?;
@@ -113,6 +174,19 @@ fn expand_notation(
                 expanded_right,
             )))
         }
+        Expr::Record(base, fields) => Ok(Rc::new(Expr::Record(
+            base.as_ref()
+                .map(|base| expand_notation(base, bindings))
+                .transpose()?,
+            fields
+                .iter()
+                .map(|(name, e)| Ok((name.clone(), expand_notation(e, bindings)?)))
+                .collect::<Result<_, String>>()?,
+        ))),
+        Expr::Field(record, name) => Ok(Rc::new(Expr::Field(
+            expand_notation(record, bindings)?,
+            name.clone(),
+        ))),
         _ => Ok(Rc::new(expansion.clone())),
     }
 }
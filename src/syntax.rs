@@ -1,27 +1,33 @@
 // syntax.rs
 
+use crate::ffi::Promise;
+use num_bigint::BigInt;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum Primitive {
     Int(i64),
+    BigInt(BigInt), // only integers that do not fit in an `Int`
     Float(f64),
     String(String),
     Bool(bool),
-    // TODO: need to define what exprs can actually be in an array better
-    Array(Vec<Rc<Expr>>),
+    None, // what statements, empty bodies and functions returning nothing evaluate to
+    Array(Vec<Primitive>),
+    Record(BTreeMap<String, Primitive>), // fields in name order
 }
 
 impl fmt::Display for Primitive {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Primitive::Int(i) => write!(f, "{}", i),
+            Primitive::BigInt(i) => write!(f, "{}", i),
             Primitive::Float(fl) => write!(f, "{}", fl),
             Primitive::String(s) => write!(f, "\"{}\"", s),
             Primitive::Bool(b) => write!(f, "{}", b),
+            Primitive::None => write!(f, "none"),
             Primitive::Array(arr) => {
                 write!(f, "[")?;
                 for (i, p) in arr.iter().enumerate() {
@@ -32,10 +38,44 @@ impl fmt::Display for Primitive {
                 }
                 write!(f, "]")
             }
+            Primitive::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field_name(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Primitive {
+    // an integer of any size, as an `Int` when it fits
+    pub fn integer(i: BigInt) -> Primitive {
+        match i64::try_from(&i) {
+            Ok(i) => Primitive::Int(i),
+            Err(_) => Primitive::BigInt(i),
         }
     }
 }
 
+// fields that are not identifiers are written as strings: `{"content-type": "text/plain"}`
+pub fn field_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct NotationPattern {
     pub pattern: String,
@@ -65,32 +105,311 @@ pub enum Associativity {
     None,
 }
 
+// `any` is what unannotated code has: it is consistent with every other type
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Type {
+    Int,
+    // integers of a C width, which `int` (a C `int`) does not have; elsewhere they are `int`
+    Long,
+    I64,
+    SizeT,
+    Float,
+    String,
+    Bool,
+    None,
+    #[default]
+    Any,
+    Array(Box<Type>),
+    Record,
+    Function(Vec<Type>, Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Long => write!(f, "long"),
+            Type::I64 => write!(f, "i64"),
+            Type::SizeT => write!(f, "size_t"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::None => write!(f, "none"),
+            Type::Any => write!(f, "any"),
+            Type::Array(element) => write!(f, "array[{}]", element),
+            Type::Record => write!(f, "record"),
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "({}) -> {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+impl Type {
+    // whether a primitive value has this type; an int is a float too
+    pub fn admits(&self, value: &Primitive) -> bool {
+        match (self, value) {
+            (Type::Any, _) => true,
+            (ty, Primitive::Int(_) | Primitive::BigInt(_)) if ty.is_integer() => true,
+            (Type::Float, Primitive::Int(_) | Primitive::BigInt(_) | Primitive::Float(_)) => true,
+            (Type::String, Primitive::String(_)) => true,
+            (Type::Bool, Primitive::Bool(_)) => true,
+            (Type::None, Primitive::None) => true,
+            (Type::Array(element), Primitive::Array(elements)) => {
+                elements.iter().all(|value| element.admits(value))
+            }
+            (Type::Record, Primitive::Record(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::Long | Type::I64 | Type::SizeT)
+    }
+}
+
+// optional types of a galois function's parameters and result, `any` where there are none
+#[derive(Clone, Debug, Default)]
+pub struct Annotations {
+    pub params: Vec<Type>, // one per parameter
+    pub ret: Type,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.ret == Type::Any && self.params.iter().all(|param| *param == Type::Any)
+    }
+
+    // `(int, any) -> string`
+    pub fn function_type(&self) -> Type {
+        Type::Function(self.params.clone(), Box::new(self.ret.clone()))
+    }
+}
+
+// declared shape of a foreign function; no return type means it returns nothing
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Option<Type>,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")?;
+        if let Some(ret) = &self.ret {
+            write!(f, " -> {}", ret)?;
+        }
+        Ok(())
+    }
+}
+
+// `#[name]` or `#[name(args)]` in front of a `from ... use` declaration
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<Primitive>,
+    pub kwargs: Vec<(String, Primitive)>,
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#[{}", self.name)?;
+        if !self.args.is_empty() || !self.kwargs.is_empty() {
+            let args = self.args.iter().map(|arg| arg.to_string());
+            let kwargs = self
+                .kwargs
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value));
+            write!(f, "({})", args.chain(kwargs).collect::<Vec<_>>().join(", "))?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CatchClause {
+    pub kinds: Vec<String>, // empty catches every error
+    pub binding: Option<String>,
+    pub body: Vec<Rc<Expr>>,
+}
+
+impl fmt::Display for CatchClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "catch")?;
+        if !self.kinds.is_empty() {
+            write!(f, " {}", self.kinds.join(", "))?;
+        }
+        if let Some(binding) = &self.binding {
+            write!(f, " as {}", binding)?;
+        }
+        Ok(())
+    }
+}
+
+// functions of the interpreter itself, which interpolated strings are desugared to. their names
+// cannot be written in a script, so nothing can shadow them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Builtin {
+    Format, // `$format(value, spec)`: a value as a string
+    Concat, // `$concat(parts)`: an array of strings joined
+}
+
+impl Builtin {
+    pub fn named(name: &str) -> Option<Builtin> {
+        match name {
+            "$format" => Some(Builtin::Format),
+            "$concat" => Some(Builtin::Concat),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Format => "$format",
+            Builtin::Concat => "$concat",
+        }
+    }
+
+    pub fn function_type(self) -> Type {
+        let params = match self {
+            Builtin::Format => vec![Type::Any, Type::String],
+            Builtin::Concat => vec![Type::Array(Box::new(Type::String))],
+        };
+        Type::Function(params, Box::new(Type::String))
+    }
+}
+
+// what follows the `:` in a hole of an interpolated string,
+// `[[fill]align][+][0][width][.precision][f|e|%]`: `{x:.2f}`, `{name:>10}`, `{ratio:+08.1%}`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<char>, // `<`, `>` or `^`
+    pub sign: bool,
+    pub zero: bool,
+    pub width: usize,
+    pub precision: Option<usize>,
+    pub kind: Option<char>, // `f`, `e` or `%`, which take numbers only
+}
+
+impl FormatSpec {
+    // widths and precisions past this are typos, and would take that much memory to honor
+    pub const MAX_WIDTH: usize = 4096;
+
+    pub fn parse(spec: &str) -> Option<FormatSpec> {
+        let mut rest = spec;
+        let mut format = FormatSpec {
+            fill: ' ',
+            align: None,
+            sign: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            kind: None,
+        };
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(align @ ('<' | '>' | '^'))) => {
+                format.fill = fill;
+                format.align = Some(align);
+                rest = chars.as_str();
+            }
+            (Some(align @ ('<' | '>' | '^')), _) => {
+                format.align = Some(align);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+        if let Some(after) = rest.strip_prefix('+') {
+            format.sign = true;
+            rest = after;
+        }
+        if let Some(after) = rest.strip_prefix('0') {
+            format.zero = true;
+            rest = after;
+        }
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 {
+            format.width = rest[..digits]
+                .parse()
+                .ok()
+                .filter(|&width| width <= Self::MAX_WIDTH)?;
+            rest = &rest[digits..];
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            format.precision = Some(
+                after[..digits]
+                    .parse()
+                    .ok()
+                    .filter(|&precision| precision <= Self::MAX_WIDTH)?,
+            );
+            rest = &after[digits..];
+        }
+        match rest {
+            "" => {}
+            "f" | "e" | "%" => format.kind = rest.chars().next(),
+            _ => return None,
+        }
+        Some(format)
+    }
+}
+
 #[derive(Clone)]
 pub enum Expr {
     Primitive(Primitive),
+    Array(Vec<Rc<Expr>>),
     Variable(String),
-    FunctionDef(String, Vec<String>, Vec<Rc<Expr>>),
-    FunctionCall(Rc<Expr>, Vec<Rc<Expr>>),
+    FunctionDef(String, Vec<String>, Vec<Rc<Expr>>, Annotations),
+    FunctionCall(Rc<Expr>, Vec<Rc<Expr>>, Vec<(String, Rc<Expr>)>),
     Return(Rc<Expr>),
     Assignment(String, Rc<Expr>),
-    FFIDecl(String, String, Option<String>),
+    FFIDecl(
+        String,
+        String,
+        Option<String>,
+        Option<Signature>,
+        Vec<Attribute>,
+    ),
+    ModuleDecl(String, Option<String>),
     InfixOp(Rc<Expr>, String, Rc<Expr>),
     NotationDecl(NotationPattern, Rc<Expr>),
+    Try(Vec<Rc<Expr>>, Vec<CatchClause>),
+    Record(Option<Rc<Expr>>, Vec<(String, Rc<Expr>)>), // `{a: 1}`, or `{r with a: 1}`
+    Field(Rc<Expr>, String), // `r.a`, or `m.f` for a module bound by `use`
 }
 
 #[derive(Clone)]
 pub enum Value {
     Primitive(Primitive),
-    Function(String, Vec<String>, Vec<Rc<Expr>>, Rc<RefCell<Environment>>),
+    Function(
+        String,
+        Vec<String>,
+        Vec<Rc<Expr>>,
+        Rc<RefCell<Environment>>,
+        Annotations,
+    ),
     Ffi(String),
+    Module(String),
+    Glob(Vec<String>), // unqualified name brought in by `use *`, with every module providing it
     PartialApplication(Rc<Value>, Vec<Value>),
+    Pending(Promise), // result of a foreign call still running under `--parallel`
+    Builtin(Builtin),
 }
 
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Primitive(p) => write!(f, "{}", p),
-            Value::Function(name, params, body, _) => {
+            Value::Function(name, params, body, ..) => {
                 write!(f, "function {} ({}) {{ ", name, params.join(", "))?;
                 body.iter().for_each(|e| {
                     let _ = fmt::Debug::fmt(e, f);
@@ -102,6 +421,10 @@ impl fmt::Debug for Value {
                 write!(f, "partial application of {:?} with {:?}", func, args)
             }
             Value::Ffi(s) => write!(f, "{}", s),
+            Value::Module(s) => write!(f, "module {}", s),
+            Value::Glob(candidates) => write!(f, "{}", candidates.join(" | ")),
+            Value::Pending(_) => write!(f, "<pending>"),
+            Value::Builtin(builtin) => write!(f, "{}", builtin.name()),
         }
     }
 }
@@ -110,7 +433,7 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Primitive(p) => write!(f, "{}", p),
-            Value::Function(name, params, body, _) => {
+            Value::Function(name, params, body, ..) => {
                 write!(f, "function {} ({}) {{ ", name, params.join(", "))?;
                 body.iter().for_each(|e| {
                     let _ = fmt::Display::fmt(e, f);
@@ -127,6 +450,10 @@ impl fmt::Display for Value {
                 )
             }
             Value::Ffi(s) => write!(f, "{}", s),
+            Value::Module(s) => write!(f, "module {}", s),
+            Value::Glob(candidates) => write!(f, "{}", candidates.join(" | ")),
+            Value::Pending(_) => write!(f, "<pending>"),
+            Value::Builtin(builtin) => write!(f, "{}", builtin.name()),
         }
     }
 }
@@ -135,16 +462,43 @@ impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Expr::Primitive(p) => write!(f, "{:?}", p),
+            Expr::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?
+                    }
+                    fmt::Debug::fmt(element, f)?;
+                }
+                write!(f, "]")
+            }
             Expr::Variable(name) => write!(f, "{}", name),
-            Expr::FunctionDef(name, params, body) => {
-                write!(f, "function {} ({}) {{ ", name, params.join(", "))?;
+            Expr::FunctionDef(name, params, body, annotations) => {
+                let params: Vec<String> = params
+                    .iter()
+                    .zip(
+                        annotations
+                            .params
+                            .iter()
+                            .chain(std::iter::repeat(&Type::Any)),
+                    )
+                    .map(|(param, annotation)| match annotation {
+                        Type::Any => param.clone(),
+                        annotation => format!("{}: {}", param, annotation),
+                    })
+                    .collect();
+                write!(f, "function {} ({})", name, params.join(", "))?;
+                if annotations.ret != Type::Any {
+                    write!(f, " -> {}", annotations.ret)?;
+                }
+                write!(f, " {{ ")?;
                 body.iter().for_each(|e| {
                     let _ = fmt::Debug::fmt(e, f);
                     write!(f, "; ").unwrap();
                 });
                 write!(f, " }}")
             }
-            Expr::FunctionCall(func, args) => {
+            Expr::FunctionCall(func, args, kwargs) => {
                 fmt::Debug::fmt(func, f)?;
                 write!(f, "(")?;
                 for (i, arg) in args.iter().enumerate() {
@@ -153,6 +507,13 @@ impl fmt::Debug for Expr {
                     }
                     fmt::Debug::fmt(arg, f)?;
                 }
+                for (i, (name, arg)) in kwargs.iter().enumerate() {
+                    if i > 0 || !args.is_empty() {
+                        write!(f, ", ")?
+                    }
+                    write!(f, "{} = ", name)?;
+                    fmt::Debug::fmt(arg, f)?;
+                }
                 write!(f, ")")
             }
             Expr::Return(e) => {
@@ -163,30 +524,196 @@ impl fmt::Debug for Expr {
                 write!(f, "{} = ", name)?;
                 fmt::Debug::fmt(e, f)
             }
-            Expr::FFIDecl(module, name, given_name) => match given_name {
-                Some(given_name) => {
-                    write!(
-                        f,
-                        "FFI Declaration: from {} use {} as {}",
-                        module, name, given_name
-                    )
+            Expr::FFIDecl(module, name, given_name, signature, attributes) => {
+                write!(f, "FFI Declaration: ")?;
+                for attribute in attributes {
+                    write!(f, "{} ", attribute)?;
                 }
-                None => {
-                    write!(f, "FFI Declaration: from {} use {}", module, name)
+                write!(f, "from {} use {}", module, name)?;
+                if let Some(signature) = signature {
+                    write!(f, "{}", signature)?;
                 }
+                if let Some(given_name) = given_name {
+                    write!(f, " as {}", given_name)?;
+                }
+                Ok(())
+            }
+            Expr::ModuleDecl(module, alias) => match alias {
+                Some(alias) => write!(f, "Module Declaration: use {} as {}", module, alias),
+                None => write!(f, "Module Declaration: use {}", module),
             },
             Expr::NotationDecl(pattern, expansion) => {
                 write!(f, "notation declaration: {} -> ", pattern)?;
                 fmt::Debug::fmt(expansion, f)
             }
             Expr::InfixOp(left, op, right) => write!(f, "({:?} {} {:?})", left, op, right),
+            Expr::Try(body, clauses) => {
+                write!(f, "try {{ ")?;
+                body.iter().for_each(|e| {
+                    let _ = fmt::Debug::fmt(e, f);
+                    write!(f, "; ").unwrap();
+                });
+                write!(f, "}}")?;
+                for clause in clauses {
+                    write!(f, " {} {{ ", clause)?;
+                    clause.body.iter().for_each(|e| {
+                        let _ = fmt::Debug::fmt(e, f);
+                        write!(f, "; ").unwrap();
+                    });
+                    write!(f, "}}")?;
+                }
+                Ok(())
+            }
+            Expr::Record(base, fields) => {
+                write!(f, "{{")?;
+                if let Some(base) = base {
+                    fmt::Debug::fmt(base, f)?;
+                    write!(f, " with ")?;
+                }
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", field_name(name))?;
+                    fmt::Debug::fmt(value, f)?;
+                }
+                write!(f, "}}")
+            }
+            Expr::Field(record, name) => {
+                fmt::Debug::fmt(record, f)?;
+                write!(f, ".{}", name)
+            }
         }
     }
 }
 
+// as the expression would be written in a script, for messages about it
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Expr::Primitive(p) => write!(f, "{}", p),
+            Expr::Array(elements) => write!(f, "[{}]", joined(elements, ", ")),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::FunctionDef(name, params, body, annotations) => {
+                let params: Vec<String> = params
+                    .iter()
+                    .zip(
+                        annotations
+                            .params
+                            .iter()
+                            .chain(std::iter::repeat(&Type::Any)),
+                    )
+                    .map(|(param, annotation)| match annotation {
+                        Type::Any => param.clone(),
+                        annotation => format!("{}: {}", param, annotation),
+                    })
+                    .collect();
+                write!(f, "fun {}({})", name, params.join(", "))?;
+                if annotations.ret != Type::Any {
+                    write!(f, " -> {}", annotations.ret)?;
+                }
+                write!(f, " {{ {} }}", joined(body, "; "))
+            }
+            Expr::FunctionCall(func, args, _) if interpolated(func, args).is_some() => {
+                write!(f, "f\"")?;
+                for part in interpolated(func, args).unwrap_or_default() {
+                    match &**part {
+                        Expr::Primitive(Primitive::String(text)) => {
+                            write!(f, "{}", text.replace('{', "{{").replace('}', "}}"))?
+                        }
+                        Expr::FunctionCall(_, hole, _) => match hole.as_slice() {
+                            [expr, spec] => match &**spec {
+                                Expr::Primitive(Primitive::String(spec)) if spec.is_empty() => {
+                                    write!(f, "{{{}}}", expr)?
+                                }
+                                Expr::Primitive(Primitive::String(spec)) => {
+                                    write!(f, "{{{}:{}}}", expr, spec)?
+                                }
+                                _ => write!(f, "{{{}}}", part)?,
+                            },
+                            _ => write!(f, "{{{}}}", part)?,
+                        },
+                        part => write!(f, "{{{}}}", part)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expr::FunctionCall(func, args, kwargs) => {
+                let args = args.iter().map(|arg| arg.to_string());
+                let kwargs = kwargs
+                    .iter()
+                    .map(|(name, arg)| format!("{} = {}", name, arg));
+                write!(
+                    f,
+                    "{}({})",
+                    func,
+                    args.chain(kwargs).collect::<Vec<_>>().join(", ")
+                )
+            }
+            Expr::Return(e) => write!(f, "return {}", e),
+            Expr::Assignment(name, e) => write!(f, "{} = {}", name, e),
+            Expr::FFIDecl(module, name, given_name, signature, attributes) => {
+                for attribute in attributes {
+                    write!(f, "{} ", attribute)?;
+                }
+                write!(f, "from {} use {}", module, name)?;
+                if let Some(signature) = signature {
+                    write!(f, "{}", signature)?;
+                }
+                if let Some(given_name) = given_name {
+                    write!(f, " as {}", given_name)?;
+                }
+                Ok(())
+            }
+            Expr::ModuleDecl(module, alias) => match alias {
+                Some(alias) => write!(f, "use {} as {}", module, alias),
+                None => write!(f, "use {}", module),
+            },
+            Expr::NotationDecl(pattern, expansion) => {
+                write!(f, "notation \"{}\" := {}", pattern.pattern, expansion)
+            }
+            Expr::InfixOp(left, op, right) => write!(f, "({} {} {})", left, op, right),
+            Expr::Try(body, clauses) => {
+                write!(f, "try {{ {} }}", joined(body, "; "))?;
+                for clause in clauses {
+                    write!(f, " {} {{ {} }}", clause, joined(&clause.body, "; "))?;
+                }
+                Ok(())
+            }
+            Expr::Record(base, fields) => {
+                write!(f, "{{")?;
+                if let Some(base) = base {
+                    write!(f, "{} with ", base)?;
+                }
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", field_name(name), value))
+                    .collect();
+                write!(f, "{}}}", fields.join(", "))
+            }
+            Expr::Field(record, name) => write!(f, "{}.{}", record, name),
+        }
+    }
+}
+
+fn joined(exprs: &[Rc<Expr>], separator: &str) -> String {
+    exprs
+        .iter()
+        .map(|expr| expr.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+// the parts of an interpolated string, when a call is what one is desugared to
+fn interpolated<'a>(func: &Expr, args: &'a [Rc<Expr>]) -> Option<&'a [Rc<Expr>]> {
+    match (func, args) {
+        (Expr::Variable(name), [parts]) if Builtin::named(name) == Some(Builtin::Concat) => {
+            match &**parts {
+                Expr::Array(parts) => Some(parts),
+                _ => None,
+            }
+        }
+        _ => None,
     }
 }
 
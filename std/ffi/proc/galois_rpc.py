@@ -0,0 +1,80 @@
+# std/ffi/proc/galois_rpc.py
+#
+# reference implementation of the galois proc protocol (see docs/protocol.md).
+# a proc module is any executable; with this helper a python one is just:
+#
+#     from galois_rpc import serve
+#
+#     def upper(s):
+#         return s.upper()
+#
+#     serve([upper])
+
+import json
+import sys
+import traceback
+
+PARSE_ERROR = -32700
+INVALID_REQUEST = -32600
+METHOD_NOT_FOUND = -32601
+CALL_FAILED = -32000
+
+
+def _error(code, message, exc=None):
+    error = {"code": code, "message": message}
+    if exc is not None:
+        error["data"] = {
+            "kind": type(exc).__name__,
+            "bases": [base.__name__ for base in type(exc).__mro__[1:] if base is not object],
+            "traceback": "".join(traceback.format_exception(type(exc), exc, exc.__traceback__)),
+        }
+    return error
+
+
+def _handle(functions, request):
+    method = request.get("method")
+    if method == "rpc.list":
+        return {"result": sorted(functions)}
+    if method not in functions:
+        return {"error": _error(METHOD_NOT_FOUND, f"no such function: {method}")}
+
+    params = request.get("params") or {}
+    if isinstance(params, list):
+        params = {"args": params}
+    try:
+        result = functions[method](*params.get("args", []), **params.get("kwargs", {}))
+    except Exception as exc:
+        return {"error": _error(CALL_FAILED, str(exc), exc)}
+    return {"result": result}
+
+
+def serve(functions, stdin=None, stdout=None):
+    """answers requests until stdin is closed. `functions` is a dict of name -> callable,
+    or a list of callables exported under their own names."""
+    if not isinstance(functions, dict):
+        functions = {f.__name__: f for f in functions}
+    stdin = stdin or sys.stdin
+    stdout = stdout or sys.stdout
+    # stdout carries the protocol, anything the functions print goes to stderr
+    sys.stdout = sys.stderr
+
+    for line in stdin:
+        if not line.strip():
+            continue
+        try:
+            request = json.loads(line)
+        except ValueError as exc:
+            response = {"id": None, "error": _error(PARSE_ERROR, str(exc))}
+        else:
+            if not isinstance(request, dict):
+                response = {"id": None, "error": _error(INVALID_REQUEST, "expected an object")}
+            else:
+                response = {"id": request.get("id"), **_handle(functions, request)}
+        try:
+            encoded = json.dumps({"jsonrpc": "2.0", **response})
+        except TypeError as exc:
+            encoded = json.dumps(
+                {"jsonrpc": "2.0", "id": response["id"], "error": _error(CALL_FAILED, str(exc), exc)}
+            )
+        stdout.write(encoded + "\n")
+        stdout.flush()
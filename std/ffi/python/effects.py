@@ -1,5 +1,8 @@
 # std/ffi/python/effects.py
 
+import galois_ffi
 
+
+@galois_ffi.effect("stdout")
 def print_value(x):
     print(x)
@@ -0,0 +1,54 @@
+# std/ffi/python/galois_ffi.py
+#
+# helpers for python modules called from galois, importable as `galois_ffi` from any of them
+
+
+def batch(function=None, *, max_size=None, window_ms=10):
+    """Marks a function as batchable: it takes a list of argument lists and returns a list
+    with one result for each. galois sends it the calls that are pending at the same time,
+    at most `max_size` of them, waiting up to `window_ms` for more after the first."""
+
+    def mark(function):
+        function.__galois_batch__ = {"max_size": max_size, "window_ms": window_ms}
+        return function
+
+    return mark(function) if function is not None else mark
+
+
+def pure(function):
+    """Declares that a function does nothing but compute its result: galois may cache it,
+    and under --parallel run it at the same time as anything else."""
+    function.__galois_effect__ = []
+    return function
+
+
+def io(function):
+    """Declares that a function may do anything. This is what an undeclared function is
+    assumed to do."""
+    function.__galois_effect__ = None
+    return function
+
+
+def effect(*channels):
+    """Declares the effects of a function by name, e.g. `@effect("stdout")`. Under
+    --parallel, calls sharing an effect keep their order, other calls may overlap them."""
+
+    def mark(function):
+        function.__galois_effect__ = list(channels)
+        return function
+
+    return mark
+
+
+def affine(function):
+    """Declares that what a function returns, a handle or a session, may be used at most once
+    by a script. `galois --affine` checks this before running it."""
+    function.__galois_ownership__ = "affine"
+    return function
+
+
+def linear(function):
+    """Declares that what a function returns must be used exactly once, e.g. a transaction
+    that has to be committed or rolled back. `galois --affine` checks this before running."""
+    function.__galois_ownership__ = "linear"
+    return function
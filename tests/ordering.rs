@@ -0,0 +1,111 @@
+// tests/ordering.rs
+//
+// effects on one channel happen in program order under `--parallel`, whatever the schedule:
+// every script here prints the same under many `--schedule-seed`s as it does sequentially.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const SEEDS: u64 = 16;
+
+// a scratch directory for the files the scripts write, empty for each run
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("galois-ordering-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// stdout of a run, without the timing line
+fn run(script: &str, scratch: &Path, args: &[&str]) -> String {
+    for entry in fs::read_dir(scratch).unwrap() {
+        fs::remove_file(entry.unwrap().path()).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_galois"))
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join(script))
+        .arg("--no-cache")
+        .arg("--path")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("std"))
+        .args(args)
+        .env("GALOIS_ORDERING_DIR", scratch)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{} {:?} failed: {}",
+        script,
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with("took "))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn assert_stable(script: &str, expected: &str) {
+    let scratch = scratch(Path::new(script).file_stem().unwrap().to_str().unwrap());
+    assert_eq!(
+        run(script, &scratch, &[]),
+        expected,
+        "{} sequentially",
+        script
+    );
+    assert_eq!(
+        run(script, &scratch, &["--parallel"]),
+        expected,
+        "{} --parallel",
+        script
+    );
+    for seed in 0..SEEDS {
+        let seed = seed.to_string();
+        assert_eq!(
+            run(script, &scratch, &["--schedule-seed", &seed]),
+            expected,
+            "{} --schedule-seed {}",
+            script,
+            seed
+        );
+    }
+    let _ = fs::remove_dir_all(scratch);
+}
+
+#[test]
+fn list_prints_in_order() {
+    assert_stable("examples/list.gal", "0\n1\n2\n3\n");
+}
+
+#[test]
+fn channels_keep_program_order() {
+    assert_stable(
+        "tests/ordering/channels.gal",
+        "1\n4\n['1', '9']\n['1', '2', '16']\n3\n['x', 'y']\n",
+    );
+}
+
+#[test]
+fn pure_calls_overlap() {
+    let scratch = scratch("overlap");
+    let timed = |args: &[&str]| {
+        let started = Instant::now();
+        let output = run("tests/ordering/overlap.gal", &scratch, args);
+        (output, started.elapsed())
+    };
+    let (sequential_output, sequential) = timed(&[]);
+    let (output, parallel) = timed(&["--parallel"]);
+    // eight calls of 0.3s each: run one after another they take 2.4s, overlapped far less, and
+    // start-up costs the same either way
+    assert!(
+        parallel + Duration::from_millis(1200) < sequential,
+        "--parallel took {:?}, sequentially {:?}",
+        parallel,
+        sequential
+    );
+    assert_eq!(output, "[1, 2, 3, 4, 5, 6, 7, 8]\n");
+    assert_eq!(sequential_output, output);
+    let _ = fs::remove_dir_all(scratch);
+}
@@ -0,0 +1,64 @@
+# tests/ordering/ffi/python/ordering.py
+#
+# functions with every kind of effect, each sleeping a random moment so that any call not
+# ordered by its effect is likely to overtake another. files live in $GALOIS_ORDERING_DIR.
+
+import os
+import random
+import time
+
+import galois_ffi
+
+
+def _jitter():
+    time.sleep(random.random() * 0.01)
+
+
+def _path(name):
+    return os.path.join(os.environ["GALOIS_ORDERING_DIR"], name)
+
+
+@galois_ffi.pure
+def square(x):
+    _jitter()
+    return x * x
+
+
+@galois_ffi.pure
+def slow(x):
+    time.sleep(0.3)
+    return x
+
+
+@galois_ffi.pure
+def same(x):
+    _jitter()
+    return x
+
+
+@galois_ffi.effect("stdout")
+def emit(x):
+    _jitter()
+    print(x, flush=True)
+    return x
+
+
+@galois_ffi.effect("file:{0}")
+def append(name, line):
+    _jitter()
+    with open(_path(name), "a") as f:
+        f.write(f"{line}\n")
+    return line
+
+
+@galois_ffi.effect("file:{0}")
+def contents(name):
+    with open(_path(name)) as f:
+        return f.read().split()
+
+
+def log(line):
+    _jitter()
+    with open(_path("log"), "a") as f:
+        f.write(f"{line}\n")
+    return line